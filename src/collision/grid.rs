@@ -0,0 +1,392 @@
+extern crate tiled;
+
+use std::collections::HashMap;
+
+use rect::Rect;
+use rendering::split_gid;
+use tmx::InfiniteLayer;
+
+// Collision shape from the object layer Tiled lets you draw inside a tile, in world space
+#[derive(Clone, Debug)]
+pub enum CollisionShape {
+    Rect(Rect),
+    Polygon(Vec<[f32; 2]>),
+}
+
+impl CollisionShape {
+    pub fn bounds(&self) -> Rect {
+        match *self {
+            CollisionShape::Rect(rect) => rect,
+            CollisionShape::Polygon(ref points) => {
+                let mut min = [::std::f32::MAX, ::std::f32::MAX];
+                let mut max = [::std::f32::MIN, ::std::f32::MIN];
+                for point in points.iter() {
+                    min[0] = min[0].min(point[0]);
+                    min[1] = min[1].min(point[1]);
+                    max[0] = max[0].max(point[0]);
+                    max[1] = max[1].max(point[1]);
+                }
+                Rect::new(min[0], min[1], max[0] - min[0], max[1] - min[1])
+            },
+        }
+    }
+}
+
+// What a tileset tile contributes to collision, in tile local pixels with y going down like Tiled
+#[derive(Clone)]
+struct TileCollision {
+    shapes: Vec<tiled::Object>,
+}
+
+// Which map cells are solid, built from the tiles placed on every layer. A tile is solid when
+// its tileset entry has a "solid" property set to true, or has collision shapes drawn on it.
+// Cells are addressed bottom up, the same as TileMap.
+pub struct CollisionMap {
+    width: usize,
+    height: usize,
+    tile_size: [f32; 2],
+    // the map cell the first cell is at, only infinite maps' chunks go left of or below 0, 0
+    origin: [i32; 2],
+    solid: Vec<bool>,
+    shapes: HashMap<usize, Vec<CollisionShape>>,
+    // gid of every tile on each layer, so a cell can be worked out again when one of them changes
+    layers: Vec<Vec<u32>>,
+    collisions: HashMap<u32, TileCollision>,
+}
+
+fn tile_collisions(map: &tiled::Map) -> HashMap<u32, TileCollision> {
+    let mut collisions = HashMap::new();
+    for tileset in map.tilesets.iter() {
+        for tile in tileset.tiles.iter() {
+            let solid = tile.properties.get("solid").map_or(false, |value| value == "true");
+            let shapes = match tile.objectgroup {
+                Some(ref group) => group.objects.clone(),
+                None => Vec::new(),
+            };
+            if solid || !shapes.is_empty() {
+                collisions.insert(tileset.first_gid + tile.id, TileCollision { shapes: shapes });
+            }
+        }
+    }
+    collisions
+}
+
+impl CollisionMap {
+    // a map with nothing solid in it
+    pub fn empty(width: usize, height: usize, tile_size: [f32; 2]) -> CollisionMap {
+        CollisionMap {
+            width: width,
+            height: height,
+            tile_size: tile_size,
+            origin: [0, 0],
+            solid: vec![false; width * height],
+            shapes: HashMap::new(),
+            layers: Vec::new(),
+            collisions: HashMap::new(),
+        }
+    }
+
+    pub fn new(map: &tiled::Map) -> CollisionMap {
+        let height = map.height as usize;
+        let mut collision_map = CollisionMap::empty(
+            map.width as usize, height, [map.tile_width as f32, map.tile_height as f32]
+        );
+
+        let collisions = tile_collisions(map);
+        for layer in map.layers.iter() {
+            let mut gids = vec![0; collision_map.width * height];
+            for (row, cols) in layer.tiles.iter().enumerate() {
+                let ypos = height - 1 - row;
+                for (xpos, raw_gid) in cols.iter().enumerate() {
+                    collision_map.place_tile(&mut gids, &collisions, xpos, ypos, *raw_gid);
+                }
+            }
+            collision_map.layers.push(gids);
+        }
+        collision_map.collisions = collisions;
+
+        collision_map
+    }
+
+    // For infinite maps, covering every chunk of every layer
+    pub fn from_chunks(map: &tiled::Map, layers: &[InfiniteLayer]) -> CollisionMap {
+        CollisionMap::with_chunks(map.height, [map.tile_width as f32, map.tile_height as f32], tile_collisions(map), layers)
+    }
+
+    fn with_chunks(map_height: u32, tile_size: [f32; 2], collisions: HashMap<u32, TileCollision>, layers: &[InfiniteLayer]) -> CollisionMap {
+        let chunks = || layers.iter().flat_map(|layer| layer.chunks.iter());
+        let (mut min, mut max) = ([0, 0], [0, 0]);
+        if let Some(first) = chunks().next() {
+            min = [first.x, first.bottom_row(map_height)];
+            max = min;
+        }
+        for chunk in chunks() {
+            let bottom = chunk.bottom_row(map_height);
+            min = [min[0].min(chunk.x), min[1].min(bottom)];
+            max = [max[0].max(chunk.x + chunk.width as i32), max[1].max(bottom + chunk.height as i32)];
+        }
+
+        let mut collision_map = CollisionMap::empty((max[0] - min[0]) as usize, (max[1] - min[1]) as usize, tile_size);
+        collision_map.origin = min;
+        for layer in layers.iter() {
+            let mut gids = vec![0; collision_map.width * collision_map.height];
+            for chunk in layer.chunks.iter() {
+                let left = (chunk.x - min[0]) as usize;
+                let bottom = (chunk.bottom_row(map_height) - min[1]) as usize;
+                for row in 0..chunk.height {
+                    // chunk rows go top down too
+                    let ypos = bottom + chunk.height - 1 - row;
+                    for xpos in 0..chunk.width {
+                        let raw_gid = chunk.gids[(row * chunk.width) + xpos];
+                        collision_map.place_tile(&mut gids, &collisions, left + xpos, ypos, raw_gid);
+                    }
+                }
+            }
+            collision_map.layers.push(gids);
+        }
+        collision_map.collisions = collisions;
+
+        collision_map
+    }
+
+    // while building, records a layer's tile in gids and adds it to the cell if it collides
+    fn place_tile(&mut self, gids: &mut [u32], collisions: &HashMap<u32, TileCollision>, xpos: usize, ypos: usize, raw_gid: u32) {
+        let (gid, _) = split_gid(raw_gid);
+        gids[self.calc_idx(xpos, ypos)] = gid;
+        if let Some(collision) = collisions.get(&gid) {
+            self.add_tile(xpos, ypos, collision);
+        }
+    }
+
+    // Puts raw_gid on a layer at xpos, ypos, 0 clearing it, and works out again whether that cell
+    // is solid from what's left on every layer. Used when gameplay changes the map.
+    pub fn set_tile(&mut self, layer: usize, xpos: usize, ypos: usize, raw_gid: u32) {
+        if xpos >= self.width || ypos >= self.height {
+            return
+        }
+        let idx = self.calc_idx(xpos, ypos);
+        while self.layers.len() <= layer {
+            self.layers.push(vec![0; self.width * self.height]);
+        }
+        self.layers[layer][idx] = split_gid(raw_gid).0;
+
+        let cell: Vec<TileCollision> = self.layers.iter()
+            .filter_map(|gids| self.collisions.get(&gids[idx]).cloned())
+            .collect();
+        self.set_solid(xpos, ypos, false);
+        for collision in cell.iter() {
+            self.add_tile(xpos, ypos, collision);
+        }
+    }
+
+    fn add_tile(&mut self, xpos: usize, ypos: usize, collision: &TileCollision) {
+        let idx = self.calc_idx(xpos, ypos);
+        self.solid[idx] = true;
+
+        let tile_rect = self.tile_rect(xpos, ypos);
+        let shapes = self.shapes.entry(idx).or_insert(Vec::new());
+        for object in collision.shapes.iter() {
+            // flip Tiled's y down tile space into world space
+            let to_world = |x: f32, y: f32| [tile_rect.x + object.x + x, tile_rect.top() - (object.y + y)];
+            match object.shape {
+                tiled::ObjectShape::Rect { width, height } |
+                tiled::ObjectShape::Ellipse { width, height } => {
+                    let corner = to_world(0.0, height);
+                    shapes.push(CollisionShape::Rect(Rect::new(corner[0], corner[1], width, height)));
+                },
+                tiled::ObjectShape::Polygon { ref points } => {
+                    shapes.push(CollisionShape::Polygon(points.iter().map(|&(x, y)| to_world(x, y)).collect()));
+                },
+                tiled::ObjectShape::Polyline { .. } => (),
+            }
+        }
+    }
+
+    fn calc_idx(&self, xpos: usize, ypos: usize) -> usize {
+        (ypos * self.width) + xpos
+    }
+
+    pub fn tile_rect(&self, xpos: usize, ypos: usize) -> Rect {
+        let cell = [xpos as i32 + self.origin[0], ypos as i32 + self.origin[1]];
+        Rect::new(cell[0] as f32 * self.tile_size[0], cell[1] as f32 * self.tile_size[1], self.tile_size[0], self.tile_size[1])
+    }
+
+    // cells outside of the map are never solid
+    pub fn is_solid(&self, xpos: i32, ypos: i32) -> bool {
+        if xpos < 0 || ypos < 0 || xpos as usize >= self.width || ypos as usize >= self.height {
+            return false
+        }
+        self.solid[self.calc_idx(xpos as usize, ypos as usize)]
+    }
+
+    pub fn set_solid(&mut self, xpos: usize, ypos: usize, solid: bool) {
+        let idx = self.calc_idx(xpos, ypos);
+        self.solid[idx] = solid;
+        if !solid {
+            self.shapes.remove(&idx);
+        }
+    }
+
+    pub fn shapes(&self, xpos: usize, ypos: usize) -> Option<&Vec<CollisionShape>> {
+        self.shapes.get(&self.calc_idx(xpos, ypos))
+    }
+
+    // the solid areas touching rect: the tile itself, or its collision shape bounds when it has any
+    pub fn solid_rects_overlapping(&self, rect: &Rect) -> Vec<Rect> {
+        let mut rects = Vec::new();
+        for (xpos, ypos) in self.tiles_overlapping(rect) {
+            match self.shapes(xpos, ypos) {
+                Some(shapes) if !shapes.is_empty() => {
+                    for shape in shapes.iter() {
+                        let bounds = shape.bounds();
+                        if bounds.overlaps(rect) {
+                            rects.push(bounds);
+                        }
+                    }
+                },
+                _ => rects.push(self.tile_rect(xpos, ypos)),
+            }
+        }
+        rects
+    }
+
+    // solid cells whose area overlaps rect, rect being in world pixels
+    pub fn tiles_overlapping(&self, rect: &Rect) -> Vec<(usize, usize)> {
+        let origin = [self.origin[0] as f32, self.origin[1] as f32];
+        let min_x = ((rect.x / self.tile_size[0]).floor() - origin[0]).max(0.0) as usize;
+        let min_y = ((rect.y / self.tile_size[1]).floor() - origin[1]).max(0.0) as usize;
+        let max_x = (((rect.right() / self.tile_size[0]).ceil() - origin[0]).max(0.0) as usize).min(self.width);
+        let max_y = (((rect.top() / self.tile_size[1]).ceil() - origin[1]).max(0.0) as usize).min(self.height);
+
+        let mut tiles = Vec::new();
+        for ypos in min_y..max_y {
+            for xpos in min_x..max_x {
+                if !self.solid[self.calc_idx(xpos, ypos)] || !self.tile_rect(xpos, ypos).overlaps(rect) {
+                    continue
+                }
+                let touches_shape = match self.shapes(xpos, ypos) {
+                    Some(shapes) if !shapes.is_empty() => shapes.iter().any(|shape| shape.bounds().overlaps(rect)),
+                    _ => true,
+                };
+                if touches_shape {
+                    tiles.push((xpos, ypos));
+                }
+            }
+        }
+        tiles
+    }
+}
+
+// 10x10 map of 32px tiles with a floor along the bottom row and a wall at x = 5. Gid 5 is solid
+// all over, like a tile with "solid" set, and is what they're made of
+#[cfg(test)]
+pub fn test_map() -> CollisionMap {
+    let mut map = CollisionMap::empty(10, 10, [32.0, 32.0]);
+    map.collisions.insert(5, TileCollision { shapes: Vec::new() });
+    for x in 0..10 {
+        map.set_tile(0, x, 0, 5);
+    }
+    for y in 1..10 {
+        map.set_tile(0, 5, y, 5);
+    }
+    map
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placing_and_clearing_tiles_updates_solidity() {
+        let mut map = test_map();
+        map.set_tile(0, 1, 2, 5);
+        assert!(map.is_solid(1, 2));
+        map.set_tile(0, 1, 2, 0);
+        assert!(!map.is_solid(1, 2));
+    }
+
+    #[test]
+    fn cells_stay_solid_while_any_layer_is() {
+        let mut map = test_map();
+        map.set_tile(0, 3, 3, 5);
+        map.set_tile(1, 3, 3, 5);
+        map.set_tile(0, 3, 3, 0);
+        assert!(map.is_solid(3, 3));
+        map.set_tile(1, 3, 3, 0);
+        assert!(!map.is_solid(3, 3));
+    }
+
+    #[test]
+    fn flip_bits_dont_change_solidity() {
+        let mut map = test_map();
+        map.set_tile(0, 2, 4, 5 | 0x80000000);
+        assert!(map.is_solid(2, 4));
+    }
+
+    #[test]
+    fn cells_outside_the_map_arent_solid() {
+        let map = test_map();
+        assert!(map.is_solid(0, 0));
+        assert!(!map.is_solid(-1, 0));
+        assert!(!map.is_solid(0, -1));
+        assert!(!map.is_solid(10, 0));
+        assert!(!map.is_solid(5, 10));
+    }
+
+    #[test]
+    fn tile_rects_are_in_world_pixels() {
+        let rect = test_map().tile_rect(3, 2);
+        assert_eq!((rect.x, rect.y, rect.w, rect.h), (96.0, 64.0, 32.0, 32.0));
+    }
+
+    #[test]
+    fn finds_solid_tiles_under_a_rect() {
+        let map = test_map();
+        // standing on the floor next to the wall
+        let tiles = map.tiles_overlapping(&Rect::new(140.0, 20.0, 32.0, 64.0));
+        assert_eq!(tiles, vec![(4, 0), (5, 0), (5, 1), (5, 2)]);
+        assert!(map.tiles_overlapping(&Rect::new(40.0, 40.0, 32.0, 64.0)).is_empty());
+        // rects hanging off the map only find what's on it
+        assert_eq!(map.tiles_overlapping(&Rect::new(-50.0, -50.0, 60.0, 60.0)), vec![(0, 0)]);
+    }
+
+    #[test]
+    fn solid_rects_without_shapes_are_whole_tiles() {
+        let rects = test_map().solid_rects_overlapping(&Rect::new(10.0, 10.0, 10.0, 10.0));
+        assert_eq!(rects.len(), 1);
+        assert_eq!((rects[0].x, rects[0].y, rects[0].w, rects[0].h), (0.0, 0.0, 32.0, 32.0));
+    }
+
+    #[test]
+    fn polygon_bounds_cover_every_point() {
+        let bounds = CollisionShape::Polygon(vec![[4.0, 2.0], [10.0, 8.0], [-2.0, 5.0]]).bounds();
+        assert_eq!((bounds.x, bounds.y, bounds.w, bounds.h), (-2.0, 2.0, 12.0, 6.0));
+    }
+
+    #[test]
+    fn infinite_maps_collide_with_their_chunks() {
+        use std::fs::File;
+        use tmx::read_infinite_layers;
+
+        let layers = read_infinite_layers(File::open("./tests/fixtures/infinite.tmx").unwrap()).unwrap().unwrap();
+        let mut collisions = HashMap::new();
+        collisions.insert(1, TileCollision { shapes: Vec::new() });
+        let map = CollisionMap::with_chunks(8, [32.0, 32.0], collisions, &layers);
+
+        // the chunks span map cells -4, 0 to 4, 8
+        assert_eq!((map.width, map.height, map.origin), (8, 8, [-4, 0]));
+        // the bottom left tile of the ground chunk left of the map's origin
+        assert!(map.is_solid(0, 4));
+        let rect = map.tile_rect(0, 4);
+        assert_eq!((rect.x, rect.y), (-128.0, 128.0));
+        // the walls chunk's top row and right column
+        for xpos in 4..8 {
+            assert!(map.is_solid(xpos, 7));
+        }
+        assert!(map.is_solid(7, 4));
+        assert!(!map.is_solid(4, 4));
+
+        assert_eq!(map.tiles_overlapping(&Rect::new(-120.0, 130.0, 10.0, 10.0)), vec![(0, 4)]);
+        assert!(map.tiles_overlapping(&Rect::new(40.0, 140.0, 10.0, 10.0)).is_empty());
+    }
+}
@@ -0,0 +1,5 @@
+mod grid;
+mod sweep;
+
+pub use self::grid::*;
+pub use self::sweep::*;
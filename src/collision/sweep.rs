@@ -0,0 +1,133 @@
+extern crate amethyst;
+
+use amethyst::ecs::{Component, VecStorage};
+
+use collision::CollisionMap;
+use rect::Rect;
+
+// how far away a surface can be and still count as touching
+const CONTACT_EPSILON: f32 = 0.5;
+
+// Which sides of an entity are against something solid after its last move
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Contacts {
+    pub grounded: bool,
+    pub ceiling: bool,
+    pub wall_left: bool,
+    pub wall_right: bool,
+}
+
+impl Contacts {
+    // normals of the surfaces being touched, pointing away from them
+    #[cfg(test)]
+    pub fn normals(&self) -> Vec<[f32; 2]> {
+        let mut normals = Vec::new();
+        if self.grounded {
+            normals.push([0.0, 1.0]);
+        }
+        if self.ceiling {
+            normals.push([0.0, -1.0]);
+        }
+        if self.wall_left {
+            normals.push([1.0, 0.0]);
+        }
+        if self.wall_right {
+            normals.push([-1.0, 0.0]);
+        }
+        normals
+    }
+}
+
+impl Component for Contacts {
+    type Storage = VecStorage<Contacts>;
+}
+
+// area covered by rect moving amount along axis
+fn swept_rect(rect: &Rect, amount: f32, axis: usize) -> Rect {
+    match (axis, amount > 0.0) {
+        (0, true) => Rect::new(rect.x, rect.y, rect.w + amount, rect.h),
+        (0, false) => Rect::new(rect.x + amount, rect.y, rect.w - amount, rect.h),
+        (_, true) => Rect::new(rect.x, rect.y, rect.w, rect.h + amount),
+        (_, false) => Rect::new(rect.x, rect.y + amount, rect.w, rect.h - amount),
+    }
+}
+
+// how far rect can move along one axis before hitting something solid
+fn sweep_axis(map: &CollisionMap, rect: &Rect, amount: f32, axis: usize) -> f32 {
+    if amount == 0.0 {
+        return 0.0
+    }
+    let mut allowed = amount;
+    for solid in map.solid_rects_overlapping(&swept_rect(rect, amount, axis)) {
+        // already inside it, let the entity move out rather than getting stuck
+        if solid.overlaps(rect) {
+            continue
+        }
+        let gap = match (axis, amount > 0.0) {
+            (0, true) => solid.x - rect.right(),
+            (0, false) => solid.right() - rect.x,
+            (_, true) => solid.y - rect.top(),
+            (_, false) => solid.top() - rect.y,
+        };
+        if amount > 0.0 {
+            allowed = allowed.min(gap.max(0.0));
+        } else {
+            allowed = allowed.max(gap.min(0.0));
+        }
+    }
+    allowed
+}
+
+fn touching(map: &CollisionMap, probe: Rect) -> bool {
+    !map.solid_rects_overlapping(&probe).is_empty()
+}
+
+// Moves a box centred on position by movement, resolving x then y against the solid tiles
+// so it slides along walls instead of sticking to them. Returns the new centre and what it's touching.
+pub fn sweep_aabb(map: &CollisionMap, position: [f32; 2], size: [f32; 2], movement: [f32; 2]) -> ([f32; 2], Contacts) {
+    let mut rect = Rect::from_center(position, size[0], size[1]);
+
+    rect.x += sweep_axis(map, &rect, movement[0], 0);
+    rect.y += sweep_axis(map, &rect, movement[1], 1);
+
+    let contacts = Contacts {
+        grounded: touching(map, Rect::new(rect.x, rect.y - CONTACT_EPSILON, rect.w, CONTACT_EPSILON)),
+        ceiling: touching(map, Rect::new(rect.x, rect.top(), rect.w, CONTACT_EPSILON)),
+        wall_left: touching(map, Rect::new(rect.x - CONTACT_EPSILON, rect.y, CONTACT_EPSILON, rect.h)),
+        wall_right: touching(map, Rect::new(rect.right(), rect.y, CONTACT_EPSILON, rect.h)),
+    };
+
+    ([rect.x + rect.w / 2.0, rect.y + rect.h / 2.0], contacts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use collision::test_map;
+
+    #[test]
+    fn lands_on_floor() {
+        let map = test_map();
+        let (position, contacts) = sweep_aabb(&map, [64.0, 100.0], [32.0, 64.0], [0.0, -200.0]);
+        assert_eq!(position, [64.0, 64.0]);
+        assert!(contacts.grounded);
+        assert!(!contacts.ceiling);
+    }
+
+    #[test]
+    fn stops_at_wall_without_tunnelling() {
+        let map = test_map();
+        let (position, contacts) = sweep_aabb(&map, [64.0, 64.0], [32.0, 64.0], [500.0, 0.0]);
+        assert_eq!(position, [144.0, 64.0]);
+        assert!(contacts.wall_right);
+        assert!(contacts.grounded);
+        assert_eq!(contacts.normals(), vec![[0.0, 1.0], [-1.0, 0.0]]);
+    }
+
+    #[test]
+    fn slides_along_floor() {
+        let map = test_map();
+        let (position, _) = sweep_aabb(&map, [64.0, 64.0], [32.0, 64.0], [10.0, -10.0]);
+        assert_eq!(position, [74.0, 64.0]);
+    }
+}
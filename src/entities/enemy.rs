@@ -0,0 +1,22 @@
+extern crate amethyst;
+
+use amethyst::ecs::{Component, VecStorage};
+
+// width and height of an enemy's sprite
+pub const ENEMY_SIZE: [f32; 2] = [32.0, 32.0];
+
+pub struct Enemy {
+    pub position: [f32; 2],
+}
+
+impl Enemy {
+    pub fn new(position: [f32; 2]) -> Enemy {
+        Enemy {
+            position: position,
+        }
+    }
+}
+
+impl Component for Enemy {
+    type Storage = VecStorage<Enemy>;
+}
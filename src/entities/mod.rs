@@ -1,3 +1,11 @@
+mod enemy;
 mod player;
+mod portal;
+mod spawn;
+mod trigger;
 
-pub use self::player::*;
\ No newline at end of file
+pub use self::enemy::*;
+pub use self::player::*;
+pub use self::portal::*;
+pub use self::spawn::*;
+pub use self::trigger::*;
@@ -1,8 +1,9 @@
 extern crate amethyst;
 
 use amethyst::ecs::{Component, VecStorage};
-use amethyst::renderer::{VertexPosNormal};
-use rect;
+
+// width and height of the player's box, used for both drawing and collision
+pub const PLAYER_SIZE: [f32; 2] = [32.0, 64.0];
 
 pub struct Player {
     pub position: [f32; 2],
@@ -18,10 +19,6 @@ impl Player {
             size: 1.0,
         }
     }
-
-    pub fn get_renderable_verts() -> Vec<VertexPosNormal> {
-        rect::gen_rectangle(32.0, 64.0)
-    }
 }
 
 impl Component for Player {
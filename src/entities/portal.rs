@@ -0,0 +1,26 @@
+extern crate amethyst;
+
+use amethyst::ecs::{Component, VecStorage};
+
+use level::LevelTarget;
+
+// A door or portal placed in Tiled, walking into its Trigger area takes the player to another map
+#[derive(Clone, Debug)]
+pub struct Portal {
+    pub target: LevelTarget,
+    // seconds to fade out and back in over, 0 to switch straight away
+    pub fade: f32,
+}
+
+impl Portal {
+    pub fn new(target: LevelTarget, fade: f32) -> Portal {
+        Portal {
+            target: target,
+            fade: fade,
+        }
+    }
+}
+
+impl Component for Portal {
+    type Storage = VecStorage<Portal>;
+}
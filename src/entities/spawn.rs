@@ -0,0 +1,213 @@
+extern crate amethyst;
+extern crate tiled;
+
+use std::collections::HashMap;
+
+use amethyst::ecs::{Component, Entity, Join, VecStorage, World};
+use amethyst::ecs::components::{LocalTransform, Transform};
+
+use collision::Contacts;
+use entities::{Enemy, Player, Portal, Trigger, ENEMY_SIZE, PLAYER_SIZE};
+use level::LevelTarget;
+use rendering::{Sprite, SpriteAnimation, SpriteSheet, TextureAtlas, WHITE_IMAGE};
+
+// the player is drawn over enemies
+const ENEMY_DEPTH: f32 = 0.0;
+const PLAYER_DEPTH: f32 = 1.0;
+
+// Custom properties set on the object in Tiled, passed through untouched for gameplay to read
+pub struct MapProperties(pub HashMap<String, String>);
+
+impl Component for MapProperties {
+    type Storage = VecStorage<MapProperties>;
+}
+
+// Tiled positions objects from the top left of the map with y going down, entities use
+// y up from the bottom left. Returns the centre of the object.
+pub fn object_position(map: &tiled::Map, object: &tiled::Object) -> [f32; 2] {
+    let map_height = (map.height * map.tile_height) as f32;
+    [object.x + object.width / 2.0, map_height - (object.y + object.height / 2.0)]
+}
+
+fn transform_at(position: [f32; 2]) -> LocalTransform {
+    let mut transform = LocalTransform::default();
+    transform.translation = [position[0], position[1], 0.0];
+    transform
+}
+
+// a plain white box, for things without art
+fn box_sprite(atlas: &TextureAtlas, size: [f32; 2], depth: f32) -> Sprite {
+    let region = atlas.region(WHITE_IMAGE).expect("the atlas always has the white image");
+    Sprite::new(region, size, depth)
+}
+
+// Looks up where the sheet was packed in the atlas, giving the sprite to start with and the
+// animation that moves it between the atlas uvs of each frame
+fn animated_sprite(atlas: &TextureAtlas, sheet: &SpriteSheet, size: [f32; 2], depth: f32) -> Option<(Sprite, SpriteAnimation)> {
+    let region = match atlas.region(&sheet.image) {
+        Some(region) => region,
+        None => return None,
+    };
+
+    let frames = (0..sheet.frame_count()).map(|frame| region.sub_uv(sheet.frame_uv(frame))).collect();
+    let animation = SpriteAnimation::new(sheet.clips.clone(), frames);
+    let mut sprite = Sprite::new(region, size, depth);
+    if let Some(uv) = animation.uv() {
+        sprite.uv = uv;
+    }
+    Some((sprite, animation))
+}
+
+fn spawn_player(world: &mut World, atlas: &TextureAtlas, sheets: &[SpriteSheet], position: [f32; 2], properties: MapProperties) {
+    let mut player = Player::new();
+    player.position = position;
+
+    let animated = sheets.iter()
+        .find(|sheet| sheet.name == "player")
+        .and_then(|sheet| animated_sprite(atlas, sheet, PLAYER_SIZE, PLAYER_DEPTH));
+
+    let builder = world.create_now()
+        .with(player)
+        .with(Contacts::default())
+        .with(properties)
+        .with(transform_at(position))
+        .with(Transform::default());
+
+    // without a sprite sheet the player stays a white box
+    match animated {
+        Some((sprite, animation)) => builder.with(sprite).with(animation).build(),
+        None => builder.with(box_sprite(atlas, PLAYER_SIZE, PLAYER_DEPTH)).build(),
+    };
+}
+
+// Triggers, and doors and portals which are triggers that lead somewhere
+fn spawn_trigger(world: &mut World, object: &tiled::Object, position: [f32; 2]) -> Entity {
+    let builder = world.create_now()
+        .with(Trigger::new(object.name.clone(), position, [object.width, object.height]))
+        .with(MapProperties(object.properties.clone()));
+    match object_portal(object) {
+        Some(portal) if object.obj_type != "trigger" => builder.with(portal).build(),
+        _ => builder.build(),
+    }
+}
+
+// Reads where a door or portal object leads from its target_map, target_spawn and fade
+// properties. Doors without a target_map don't go anywhere.
+fn object_portal(object: &tiled::Object) -> Option<Portal> {
+    let map = match object.properties.get("target_map") {
+        Some(map) if !map.is_empty() => map.clone(),
+        _ => return None,
+    };
+    let target = LevelTarget {
+        map: map,
+        spawn: object.properties.get("target_spawn").cloned(),
+    };
+    let fade = object.properties.get("fade").and_then(|fade| fade.parse().ok()).unwrap_or(0.0);
+    Some(Portal::new(target, fade))
+}
+
+// The player_spawn object called spawn, or the first one if it's None or no spawn has that name
+fn find_spawn<'a>(map: &'a tiled::Map, spawn: Option<&str>) -> Option<&'a tiled::Object> {
+    let spawns = || map.object_groups.iter()
+        .flat_map(|group| group.objects.iter())
+        .filter(|object| object.obj_type == "player_spawn");
+
+    spawn.and_then(|name| spawns().find(|object| object.name == name))
+        .or_else(|| spawns().next())
+}
+
+// Where spawn_map_objects puts the player
+pub enum PlayerStart {
+    // at a player_spawn object, see find_spawn
+    Spawn(Option<String>),
+    // back where they were, when the same map gets reloaded
+    Position([f32; 2]),
+}
+
+// Creates entities for the objects in every object layer, picked by the object's type.
+// Objects with a type we don't know about are left alone. Entities with a sheet of the
+// same name in sheets get animated sprites.
+pub fn spawn_map_objects(world: &mut World, map: &tiled::Map, atlas: &TextureAtlas, sheets: &[SpriteSheet], start: &PlayerStart) {
+    for group in map.object_groups.iter() {
+        for object in group.objects.iter() {
+            let position = object_position(map, object);
+            let properties = MapProperties(object.properties.clone());
+
+            match object.obj_type.as_str() {
+                "enemy" => {
+                    world.create_now()
+                        .with(box_sprite(atlas, ENEMY_SIZE, ENEMY_DEPTH))
+                        .with(Enemy::new(position))
+                        .with(properties)
+                        .with(transform_at(position))
+                        .with(Transform::default())
+                        .build();
+                },
+                "trigger" | "door" | "portal" => {
+                    spawn_trigger(world, object, position);
+                },
+                _ => (),
+            }
+        }
+    }
+
+    let spawn_name = match *start {
+        PlayerStart::Spawn(ref name) => name.as_ref().map(|name| name.as_str()),
+        PlayerStart::Position(_) => None,
+    };
+    // maps without a spawn point still get a player
+    let (mut position, properties) = match find_spawn(map, spawn_name) {
+        Some(object) => (object_position(map, object), object.properties.clone()),
+        None => ([0.0, 0.0], HashMap::new()),
+    };
+    if let PlayerStart::Position(at) = *start {
+        position = at;
+    }
+    spawn_player(world, atlas, sheets, position, MapProperties(properties));
+}
+
+// Deletes every entity spawn_map_objects created, they're all tagged with their MapProperties
+pub fn despawn_map_objects(world: &mut World) {
+    let spawned: Vec<Entity> = {
+        let entities = world.entities();
+        let properties = world.read::<MapProperties>();
+        (&entities, &properties).iter().map(|(entity, _)| entity).collect()
+    };
+    for entity in spawned {
+        world.delete_now(entity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spawned_objects_keep_their_custom_properties() {
+        let data = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.0" orientation="orthogonal" width="4" height="4" tilewidth="32" tileheight="32">
+ <objectgroup name="objects">
+  <object id="1" name="cave" type="door" x="32" y="32" width="32" height="64">
+   <properties>
+    <property name="target_map" value="cave.tmx"/>
+    <property name="locked" value="true"/>
+   </properties>
+  </object>
+ </objectgroup>
+</map>"#;
+        let map = tiled::parse(data.as_bytes()).unwrap();
+        let object = &map.object_groups[0].objects[0];
+
+        let mut world = World::new();
+        world.register::<Trigger>();
+        world.register::<Portal>();
+        world.register::<MapProperties>();
+        let entity = spawn_trigger(&mut world, object, object_position(&map, object));
+
+        let properties = world.read::<MapProperties>();
+        let properties = &properties.get(entity).unwrap().0;
+        assert_eq!(properties.get("locked").map(|value| value.as_str()), Some("true"));
+        assert_eq!(properties.get("target_map").map(|value| value.as_str()), Some("cave.tmx"));
+        assert!(world.read::<Portal>().get(entity).is_some());
+    }
+}
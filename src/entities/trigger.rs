@@ -0,0 +1,31 @@
+extern crate amethyst;
+
+use amethyst::ecs::{Component, VecStorage};
+
+// An invisible area placed in Tiled, gameplay decides what happens when it's entered
+pub struct Trigger {
+    pub name: String,
+    pub position: [f32; 2],
+    pub size: [f32; 2],
+}
+
+impl Trigger {
+    pub fn new(name: String, position: [f32; 2], size: [f32; 2]) -> Trigger {
+        Trigger {
+            name: name,
+            position: position,
+            size: size,
+        }
+    }
+
+    pub fn contains(&self, point: [f32; 2]) -> bool {
+        let half_w = self.size[0] / 2.0;
+        let half_h = self.size[1] / 2.0;
+        point[0] >= self.position[0] - half_w && point[0] <= self.position[0] + half_w &&
+            point[1] >= self.position[1] - half_h && point[1] <= self.position[1] + half_h
+    }
+}
+
+impl Component for Trigger {
+    type Storage = VecStorage<Trigger>;
+}
@@ -0,0 +1,107 @@
+extern crate image;
+extern crate tiled;
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::result;
+
+// Everything that can go wrong loading the game's assets and creating its gpu resources
+#[derive(Debug)]
+pub enum Error {
+    Io(PathBuf, io::Error),
+    Image(PathBuf, image::ImageError),
+    Tmx(PathBuf, tiled::TiledError),
+    // a data file that opened fine but doesn't contain what we expect
+    Parse(PathBuf, String),
+    // something in a map the renderer can't draw
+    Unsupported(PathBuf, String),
+    // assets that load fine on their own but can't be put together, eg into the texture atlas
+    Asset(String),
+    // a command line argument the game doesn't understand
+    Args(String),
+    Gpu(String),
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+impl Error {
+    pub fn io(path: &Path, err: io::Error) -> Error {
+        Error::Io(path.to_path_buf(), err)
+    }
+
+    pub fn parse(path: &Path, message: String) -> Error {
+        Error::Parse(path.to_path_buf(), message)
+    }
+
+    pub fn unsupported(path: &Path, feature: String) -> Error {
+        Error::Unsupported(path.to_path_buf(), feature)
+    }
+
+    // for gfx errors, which don't all implement Error
+    pub fn gpu<E: fmt::Debug>(what: &str, err: E) -> Error {
+        Error::Gpu(format!("couldn't create {}: {:?}", what, err))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref path, ref err) => write!(f, "{}: {}", path.display(), err),
+            Error::Image(ref path, ref err) => write!(f, "{}: couldn't decode image: {}", path.display(), err),
+            Error::Tmx(ref path, ref err) => write!(f, "{}: couldn't parse map: {:?}", path.display(), err),
+            Error::Parse(ref path, ref message) => write!(f, "{}: {}", path.display(), message),
+            Error::Unsupported(ref path, ref feature) => write!(f, "{}: unsupported: {}", path.display(), feature),
+            Error::Asset(ref message) => write!(f, "{}", message),
+            Error::Args(ref message) => write!(f, "{}", message),
+            Error::Gpu(ref message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Io(_, ref err) => err.description(),
+            Error::Image(_, ref err) => err.description(),
+            Error::Tmx(..) => "invalid map",
+            Error::Parse(..) => "invalid data file",
+            Error::Unsupported(..) => "unsupported map feature",
+            Error::Asset(..) => "assets don't fit together",
+            Error::Args(..) => "invalid command line argument",
+            Error::Gpu(..) => "gpu resource creation failed",
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Io(_, ref err) => Some(err),
+            Error::Image(_, ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn messages_name_the_file() {
+        let err = Error::unsupported(Path::new("resources/map.tmx"), "5 tilesets, at most 4".to_string());
+        assert_eq!(format!("{}", err), "resources/map.tmx: unsupported: 5 tilesets, at most 4");
+
+        let err = Error::io(Path::new("resources/missing.png"), io::Error::new(io::ErrorKind::NotFound, "not found"));
+        assert!(format!("{}", err).starts_with("resources/missing.png"));
+    }
+
+    #[test]
+    fn asset_and_argument_errors_arent_gpu_errors() {
+        let err = Error::Args("--pixel-perfect expects WIDTHxHEIGHT, got \"480\"".to_string());
+        assert_eq!(error::Error::description(&err), "invalid command line argument");
+        assert_eq!(format!("{}", err), "--pixel-perfect expects WIDTHxHEIGHT, got \"480\"");
+        assert_eq!(error::Error::description(&Error::Asset(String::new())), "assets don't fit together");
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use tmx;
+
+// how often the files are checked, in seconds
+const POLL_INTERVAL: f32 = 0.25;
+
+// Watches every file under a directory for changes by polling their modification times,
+// so a map or image saved in an editor can be picked up while the game is running
+pub struct ResourceWatcher {
+    dir: PathBuf,
+    modified: HashMap<PathBuf, SystemTime>,
+    since_poll: f32,
+}
+
+// Maps saved from the game. They're written while it's running, reloading for them would throw
+// away the edits that were just saved.
+fn ignored(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map(|name| name.ends_with(&format!(".{}", tmx::SAVED_EXTENSION)))
+        .unwrap_or(false)
+}
+
+// modification time of every file under dir, files that can't be read or are ignored are skipped
+fn snapshot(dir: &Path, files: &mut HashMap<PathBuf, SystemTime>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        if metadata.is_dir() {
+            snapshot(&path, files);
+        } else if ignored(&path) {
+            continue
+        } else if let Ok(modified) = metadata.modified() {
+            files.insert(path, modified);
+        }
+    }
+}
+
+// files that are new in after or have a different modification time than in before, sorted
+pub fn changed_files(before: &HashMap<PathBuf, SystemTime>, after: &HashMap<PathBuf, SystemTime>) -> Vec<PathBuf> {
+    let mut changed: Vec<PathBuf> = after.iter()
+        .filter(|&(path, modified)| before.get(path) != Some(modified))
+        .map(|(path, _)| path.clone())
+        .collect();
+    changed.sort();
+    changed
+}
+
+impl ResourceWatcher {
+    pub fn new(dir: &Path) -> ResourceWatcher {
+        let mut modified = HashMap::new();
+        snapshot(dir, &mut modified);
+        ResourceWatcher {
+            dir: dir.to_path_buf(),
+            modified: modified,
+            since_poll: 0.0,
+        }
+    }
+
+    // The files that were saved since the last poll. Only actually looks every POLL_INTERVAL.
+    pub fn poll(&mut self, delta: f32) -> Vec<PathBuf> {
+        self.since_poll += delta;
+        if self.since_poll < POLL_INTERVAL {
+            return Vec::new()
+        }
+        self.since_poll = 0.0;
+
+        let mut modified = HashMap::new();
+        snapshot(&self.dir, &mut modified);
+        let changed = changed_files(&self.modified, &modified);
+        self.modified = modified;
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn finds_modified_and_new_files() {
+        let mut before = HashMap::new();
+        before.insert(PathBuf::from("map.tmx"), at(1));
+        before.insert(PathBuf::from("maptiles.png"), at(1));
+        before.insert(PathBuf::from("old.png"), at(1));
+
+        let mut after = HashMap::new();
+        after.insert(PathBuf::from("map.tmx"), at(2));
+        after.insert(PathBuf::from("maptiles.png"), at(1));
+        after.insert(PathBuf::from("cave.tmx"), at(2));
+
+        assert_eq!(changed_files(&before, &after), vec![PathBuf::from("cave.tmx"), PathBuf::from("map.tmx")]);
+    }
+
+    #[test]
+    fn ignores_saved_maps() {
+        assert!(ignored(Path::new("./resources/map.saved.tmx")));
+        assert!(!ignored(Path::new("./resources/map.tmx")));
+        assert!(!ignored(Path::new("./resources/saved.tmx")));
+    }
+
+    #[test]
+    fn only_looks_every_interval() {
+        let mut watcher = ResourceWatcher::new(Path::new("./resources"));
+        assert!(watcher.poll(POLL_INTERVAL / 2.0).is_empty());
+        assert_eq!(watcher.since_poll, POLL_INTERVAL / 2.0);
+        watcher.poll(POLL_INTERVAL);
+        assert_eq!(watcher.since_poll, 0.0);
+    }
+}
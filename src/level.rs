@@ -0,0 +1,152 @@
+extern crate tiled;
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use error::{Error, Result};
+use rendering;
+use tmx;
+
+// the longest step a fade takes in one update, so the frame spent loading the next map
+// doesn't skip the fade back in
+const MAX_FADE_STEP: f32 = 1.0 / 30.0;
+
+// A map read from disk along with everything else the game needs out of its TMX file
+pub struct Level {
+    pub path: PathBuf,
+    pub map: tiled::Map,
+    // chunk data for infinite maps, taken when the chunked tilemap is built
+    pub infinite_layers: Option<Vec<tmx::InfiniteLayer>>,
+    // which tile layers go below the entities and which above
+    pub draw_order: tmx::DrawOrder,
+    // how the TMX file was laid out, for saving it back
+    pub layout: tmx::Layout,
+}
+
+// Opens one of the game's files, the error names it if that fails
+pub fn open(path: &Path) -> Result<File> {
+    File::open(path).map_err(|e| Error::io(path, e))
+}
+
+impl Level {
+    pub fn load(path: &Path) -> Result<Level> {
+        let map = try!(tiled::parse(try!(open(path))).map_err(|e| Error::Tmx(path.to_path_buf(), e)));
+        try!(rendering::check_supported(&map, path));
+        let infinite_layers = try!(tmx::read_infinite_layers(try!(open(path))).map_err(|e| Error::parse(path, e)));
+        let draw_order = try!(tmx::read_draw_order(try!(open(path))).map_err(|e| Error::parse(path, e)));
+        let layout = try!(tmx::read_layout(try!(open(path))).map_err(|e| Error::parse(path, e)));
+
+        Ok(Level {
+            path: path.to_path_buf(),
+            map: map,
+            infinite_layers: infinite_layers,
+            draw_order: draw_order,
+            layout: layout,
+        })
+    }
+
+    // map paths in portals are relative to the map they're in
+    pub fn resolve(&self, map: &str) -> PathBuf {
+        match self.path.parent() {
+            Some(dir) => dir.join(map),
+            None => PathBuf::from(map),
+        }
+    }
+}
+
+// Where a portal leads, the map to load and the name of the player_spawn object to start at.
+// Without a spawn the player starts at the map's first one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LevelTarget {
+    pub map: String,
+    pub spawn: Option<String>,
+}
+
+// Fading out of the current map, loading the target, then fading back in. With no fade time
+// the target is loaded on the first advance.
+pub struct Transition {
+    // taken once fully faded out
+    target: Option<LevelTarget>,
+    // seconds each way
+    fade: f32,
+    elapsed: f32,
+}
+
+impl Transition {
+    pub fn new(target: LevelTarget, fade: f32) -> Transition {
+        Transition {
+            target: Some(target),
+            fade: fade.max(0.0),
+            elapsed: 0.0,
+        }
+    }
+
+    // Moves the fade along, handing over the target when it's time to load it
+    pub fn advance(&mut self, delta: f32) -> Option<LevelTarget> {
+        self.elapsed += delta.min(MAX_FADE_STEP);
+        if self.target.is_some() && self.elapsed >= self.fade {
+            self.elapsed = 0.0;
+            return self.target.take()
+        }
+        None
+    }
+
+    pub fn finished(&self) -> bool {
+        self.target.is_none() && self.elapsed >= self.fade
+    }
+
+    // how much of the screen is covered, 0 to 1
+    pub fn opacity(&self) -> f32 {
+        if self.fade <= 0.0 {
+            return 0.0
+        }
+        let t = (self.elapsed / self.fade).min(1.0);
+        match self.target {
+            Some(_) => t,
+            None => 1.0 - t,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target() -> LevelTarget {
+        LevelTarget { map: "cave.tmx".to_string(), spawn: Some("entrance".to_string()) }
+    }
+
+    #[test]
+    fn fades_out_loads_then_fades_in() {
+        let mut transition = Transition::new(target(), 0.05);
+        assert_eq!(transition.advance(0.025), None);
+        assert!((transition.opacity() - 0.5).abs() < 1e-5);
+
+        assert_eq!(transition.advance(0.025), Some(target()));
+        assert_eq!(transition.opacity(), 1.0);
+        assert!(!transition.finished());
+
+        assert_eq!(transition.advance(0.025), None);
+        assert!((transition.opacity() - 0.5).abs() < 1e-5);
+        transition.advance(0.025);
+        assert!(transition.finished());
+        assert_eq!(transition.opacity(), 0.0);
+    }
+
+    #[test]
+    fn loads_straight_away_without_a_fade() {
+        let mut transition = Transition::new(target(), 0.0);
+        assert_eq!(transition.opacity(), 0.0);
+        assert_eq!(transition.advance(0.0), Some(target()));
+        assert!(transition.finished());
+    }
+
+    #[test]
+    fn long_frames_dont_skip_the_fade() {
+        let mut transition = Transition::new(target(), 0.5);
+        transition.advance(0.5);
+        transition.advance(0.5);
+        assert!(transition.opacity() < 0.2);
+        assert!(transition.target.is_some());
+    }
+}
@@ -1,15 +0,0 @@
-extern crate image;
-extern crate gfx;
-
-use gfx::format::Rgba8;
-
-pub fn gfx_load_texture<F, R>(factory: &mut F, path: &String) -> gfx::handle::ShaderResourceView<R, [f32; 4]>
-    where F: gfx::Factory<R>,
-        R: gfx::Resources
-{
-    let img = image::open(path).unwrap().to_rgba();
-    let (width, height) = img.dimensions();
-    let kind = gfx::texture::Kind::D2(width as u16, height as u16, gfx::texture::AaMode::Single);
-    let (_, view) = factory.create_texture_immutable_u8::<Rgba8>(kind, &[&img]).unwrap();
-    view
-}
\ No newline at end of file
@@ -5,116 +5,534 @@ extern crate genmesh;
 #[macro_use]
 extern crate gfx;
 
-use amethyst::{Application, Event, State, Trans, VirtualKeyCode, WindowEvent};
+use amethyst::{Application, ElementState, Event, State, Trans, VirtualKeyCode, WindowEvent};
 use amethyst::asset_manager::{AssetManager, DirectoryStore};
 use amethyst::config::Element;
 use amethyst::ecs::{World, Join, RunArg, System};
-use amethyst::ecs::components::{Mesh, LocalTransform, Texture, Transform};
+use amethyst::ecs::components::Texture;
+use amethyst::ecs::resources::Projection;
 use amethyst::gfx_device::DisplayConfig;
-use amethyst::renderer::{Pipeline, VertexPosNormal};
+use amethyst::renderer::{Layer, Pipeline};
 use amethyst::gfx_device::gfx_types;
 
+use std::io::Write;
 use std::path::Path;
-use std::fs::File;
-use tiled::parse;
 
-mod loader;
+mod collision;
 mod entities;
+mod error;
+mod hot_reload;
+mod level;
 mod rect;
 mod rendering;
+mod systems;
+mod tile_edits;
+mod tmx;
 
-use rendering::TileMap;
+use collision::CollisionMap;
+use error::Error;
+use level::Level;
+use rendering::{ChunkedTileMap, PixelPerfectConfig, Shared, SpriteBatch, SpriteSheet, TextureAtlas, TileMap};
 
 struct Game {
-    map: tiled::Map,
-    cfg: DisplayConfig,
-    tilemap_drawer: Option<TileMap>,
+    // the map being played, infinite ones get drawn by chunked_drawer instead of tilemap_drawer
+    level: Level,
+    // shared with the passes drawing them
+    tilemap_drawer: Option<Shared<TileMap>>,
+    chunked_drawer: Option<Shared<ChunkedTileMap>>,
+    // set to draw at a low virtual resolution and scale it up to the window
+    pixel_perfect: Option<PixelPerfectConfig>,
+    // the virtual target's texture and where the layer scaling it up sits in the pipeline,
+    // so the layer can be rebuilt for a new window size
+    upscale_source: Option<(gfx::handle::ShaderResourceView<gfx_types::Resources, [f32; 4]>, usize)>,
+    // animation clips from resources/animations.xml
+    sprite_sheets: Vec<SpriteSheet>,
+    // tileset and sprite sheet images packed together, built in on_start
+    atlas: Option<TextureAtlas>,
+    // every Sprite in the world, rebuilt each update for SpriteBatchPass to draw
+    sprite_batch: Shared<SpriteBatch>,
+    // where the map and sprites get drawn in the pipeline, replaced when the level changes
+    scene_layer: Option<usize>,
+    // the way to the next level after walking through a portal
+    transition: Option<level::Transition>,
+    // how much the screen is faded out, for FadePass
+    fade: Shared<f32>,
+    // with --hot-reload, watches resources/ to rebuild the level when something's saved
+    watcher: Option<hot_reload::ResourceWatcher>,
+    // on_start can't fail, so anything that went wrong setting up is kept for update to report
+    start_error: Option<Error>,
 }
 
-impl State for Game {
-    fn on_start(&mut self, world: &mut World, assets: &mut AssetManager, pipe: &mut Pipeline) {
-        use amethyst::ecs::resources::{Camera, InputHandler, Projection, ScreenDimensions};
-        use amethyst::renderer::Layer;
-        use amethyst::renderer::pass::{Clear, DrawFlat};
-        use rendering::{MapDrawPass};
+fn report(err: &Error) {
+    let _ = writeln!(std::io::stderr(), "error: {}", err);
+}
+
+fn load_sprite_sheets() -> error::Result<Vec<SpriteSheet>> {
+    let path = Path::new("./resources/animations.xml");
+    rendering::read_sprite_sheets(try!(level::open(path))).map_err(|e| Error::parse(path, e))
+}
+
+// the camera shows view_size pixels of the map, centred on its eye
+fn orthographic(view_size: [f32; 2]) -> Projection {
+    Projection::Orthographic {
+        left: -view_size[0] / 2.0,
+        right: view_size[0] / 2.0,
+        bottom: -view_size[1] / 2.0,
+        top: view_size[1] / 2.0,
+        near: 0.0,
+        far: 1.0,
+    }
+}
+
+impl Game {
+    // the area the camera sees, in map pixels
+    fn view_rect(world: &World) -> rect::Rect {
+        let focus = world.read_resource::<systems::CameraFocus>();
+        rect::Rect::from_center(focus.position, focus.view_size[0], focus.view_size[1])
+    }
+
+    // the target the map and sprites get drawn to
+    fn scene_target(&self) -> &'static str {
+        if self.pixel_perfect.is_some() {
+            "virtual"
+        } else {
+            "main"
+        }
+    }
+
+    // How much of the map fits on screen, the virtual resolution doesn't change with the window.
+    // Windows too big for the tilemap's gpu window get a zoomed in view.
+    fn view_size(&self, window_size: [u32; 2]) -> [f32; 2] {
+        if let Some(config) = self.pixel_perfect {
+            return [config.virtual_size[0] as f32, config.virtual_size[1] as f32]
+        }
+        let map = &self.level.map;
+        let (scale, view_size) = rendering::fit_view([window_size[0] as f32, window_size[1] as f32],
+            map.tile_width as f32, [map.width as usize, map.height as usize]);
+        if scale > 1 {
+            let _ = writeln!(std::io::stderr(), "a {}x{} window needs more tiles than the tilemap holds, drawing at {}x",
+                window_size[0], window_size[1], scale);
+        }
+        view_size
+    }
+
+    fn upscale_layer(&self, factory: &mut gfx_types::Factory, pipe: &Pipeline, window_size: [u32; 2]) -> error::Result<Option<Layer>> {
+        use amethyst::renderer::pass::Clear;
         use amethyst::renderer::target::ColorBuffer;
+        use rendering::UpscalePass;
+
+        let (config, source) = match (self.pixel_perfect, self.upscale_source.as_ref()) {
+            (Some(config), Some(&(ref source, _))) => (config, source.clone()),
+            _ => return Ok(None),
+        };
+
+        let main_target = pipe.targets.get("main").unwrap() as &Box<amethyst::renderer::Target>;
+        let main_target = main_target.downcast_ref::<ColorBuffer<gfx_types::Resources>>().unwrap();
+
+        // clearing to black letterboxes whatever the scaled image doesn't cover
+        let upscale = try!(UpscalePass::new(factory, source, config, window_size, &main_target));
+        Ok(Some(Layer::new("main", vec![
+            Clear::new([0.0, 0.0, 0.0, 1.0]),
+            Box::new(upscale),
+        ])))
+    }
+
+    // Fits everything sized after the window to its new size
+    fn resize(&mut self, world: &mut World, assets: &mut AssetManager, pipe: &mut Pipeline, window_size: [u32; 2]) -> error::Result<()> {
+        use amethyst::ecs::resources::{Camera, ScreenDimensions};
+        use amethyst::renderer::target::ColorBuffer;
+
+        if window_size[0] == 0 || window_size[1] == 0 {
+            // minimized, keep everything as it was until the window comes back
+            return Ok(())
+        }
+
+        let view_size = self.view_size(window_size);
+        let (view_changed, focus) = {
+            let focus = world.read_resource::<systems::CameraFocus>();
+            (focus.view_size != view_size, focus.position)
+        };
+
+        let factory = assets.get_loader_mut::<gfx_types::Factory>()
+            .expect("Couldn't retrieve factory.");
+
+        // the tilemap goes first, if it can't be rebuilt the view stays the size it was
+        if view_changed {
+            let scene_target = pipe.targets.get(self.scene_target()).unwrap() as &Box<amethyst::renderer::Target>;
+            let scene_target = scene_target.downcast_ref::<ColorBuffer<gfx_types::Resources>>().unwrap();
+
+            if let Some(ref tilemap) = self.tilemap_drawer {
+                try!(tilemap.lock().unwrap().resize(&self.level.map, factory, view_size, focus, &scene_target));
+            }
+        }
+
+        world.add_resource::<ScreenDimensions>(ScreenDimensions::new(window_size[0], window_size[1]));
+        world.write_resource::<Camera>().proj = orthographic(view_size);
+        world.write_resource::<systems::CameraFocus>().view_size = view_size;
+
+        if let Some(layer) = try!(self.upscale_layer(factory, pipe, window_size)) {
+            let idx = self.upscale_source.as_ref().unwrap().1;
+            pipe.layers[idx] = layer;
+        }
+        Ok(())
+    }
+
+    fn start(&mut self, world: &mut World, assets: &mut AssetManager, pipe: &mut Pipeline) -> error::Result<()> {
+        use amethyst::ecs::resources::{InputHandler, ScreenDimensions};
 
         world.add_resource::<InputHandler>(InputHandler::new());
+        world.add_resource::<systems::EnteredPortal>(systems::EnteredPortal(None));
+
+        let window_size = {
+            let dim = world.read_resource::<ScreenDimensions>();
+            [dim.w as u32, dim.h as u32]
+        };
+
+        if let Some(config) = self.pixel_perfect {
+            let factory = assets.get_loader_mut::<amethyst::gfx_device::gfx_types::Factory>()
+                .expect("Couldn't retrieve factory.");
+            let (virtual_target, virtual_view) = try!(rendering::create_virtual_target(factory, &config));
+            pipe.targets.insert("virtual".to_string(), Box::new(virtual_target));
+            // the upscale layer goes right after the scene one
+            self.upscale_source = Some((virtual_view, pipe.layers.len() + 1));
+        }
+
+        assets.register_asset::<Texture>();
+
+        assets.register_store(DirectoryStore::new("./resources"));
+
+        try!(self.enter_level(world, assets, pipe, &entities::PlayerStart::Spawn(None)));
+
+        let factory = assets.get_loader_mut::<amethyst::gfx_device::gfx_types::Factory>()
+            .expect("Couldn't retrieve factory.");
+        if let Some(layer) = try!(self.upscale_layer(factory, pipe, window_size)) {
+            pipe.layers.push(layer);
+        }
+        Ok(())
+    }
+
+    // Builds everything for the current level: its atlas, tilemap and scene layer, collision,
+    // camera and entities, replacing whatever the previous level left behind. Nothing is
+    // replaced until the gpu side has been built, so on an error the old level keeps running.
+    fn enter_level(&mut self, world: &mut World, assets: &mut AssetManager, pipe: &mut Pipeline, start: &entities::PlayerStart) -> error::Result<()> {
+        use amethyst::ecs::resources::{Camera, ScreenDimensions};
+        use amethyst::renderer::pass::{Clear, PassDescription};
+        use rendering::{ChunkDrawPass, FadePass, MapDrawPass, SpriteBatchPass, WHITE_IMAGE};
+        use amethyst::renderer::target::ColorBuffer;
+
+        let window_size = {
+            let dim = world.read_resource::<ScreenDimensions>();
+            [dim.w as u32, dim.h as u32]
+        };
+        let view_size = self.view_size(window_size);
 
+        let collision;
         {
             let factory = assets.get_loader_mut::<amethyst::gfx_device::gfx_types::Factory>()
                 .expect("Couldn't retrieve factory.");
 
-            let main_target = pipe.targets.get("main").unwrap() as &Box<amethyst::renderer::Target>;
-            let main_target = main_target.downcast_ref::<ColorBuffer<gfx_types::Resources>>().unwrap();
+            // every tileset and sprite sheet image goes into the same atlas
+            let mut atlas_images: Vec<String> = self.level.map.tilesets.iter()
+                .filter_map(|tileset| tileset.images.get(0).map(|image| image.source.clone()))
+                .collect();
+            atlas_images.extend(self.sprite_sheets.iter().map(|sheet| sheet.image.clone()));
+            atlas_images.push(WHITE_IMAGE.to_string());
+            let atlas = try!(TextureAtlas::build(factory, Path::new("./resources"), &atlas_images));
+
+            let scene_target = pipe.targets.get(self.scene_target()).unwrap() as &Box<amethyst::renderer::Target>;
+            let scene_target = scene_target.downcast_ref::<ColorBuffer<gfx_types::Resources>>().unwrap();
+
+            // built before the chunks get handed to the chunked tilemap
+            collision = match self.level.infinite_layers {
+                Some(ref infinite_layers) => CollisionMap::from_chunks(&self.level.map, infinite_layers),
+                None => CollisionMap::new(&self.level.map),
+            };
+
+            let (below, above) = self.level.draw_order.split();
+            let (tilemap_drawer, chunked_drawer, map_below, map_above): (_, _, Box<PassDescription>, Box<PassDescription>) = match self.level.infinite_layers.take() {
+                Some(infinite_layers) => {
+                    let chunked_drawer = rendering::shared(try!(ChunkedTileMap::new(&self.level.map, infinite_layers, &atlas, factory)));
 
-            let dimensions = self.cfg.dimensions.unwrap();
-            self.tilemap_drawer = Some(TileMap::new(&self.map, factory, (dimensions.0 / dimensions.1) as f32, &main_target));
+                    (None, Some(chunked_drawer.clone()),
+                        Box::new(ChunkDrawPass::new(chunked_drawer.clone(), below)), Box::new(ChunkDrawPass::new(chunked_drawer, above)))
+                },
+                None => {
+                    let tilemap_drawer = rendering::shared(try!(TileMap::new(&self.level.map, factory, &atlas, view_size, &scene_target)));
 
-            let tilemap_drawer = &self.tilemap_drawer.unwrap();
-            let layer = Layer::new("main", vec![
+                    (Some(tilemap_drawer.clone()), None,
+                        Box::new(try!(MapDrawPass::new(tilemap_drawer.clone(), below, factory))), Box::new(try!(MapDrawPass::new(tilemap_drawer, above, factory))))
+                },
+            };
+
+            let sprite_pass = try!(SpriteBatchPass::new(factory, self.sprite_batch.clone(), &atlas, &scene_target));
+            let fade_pass = try!(FadePass::new(factory, [0.0, 0.0, 0.0], self.fade.clone(), &scene_target));
+
+            // entity sprites go between the tile layers below and above them, eg behind tree canopies
+            let layer = Layer::new(self.scene_target(), vec![
                 Clear::new([0.0, 0.0, 0.0, 1.0]),
-                Box::new(MapDrawPass::new(tilemap_drawer, factory)),
-                DrawFlat::new("main", "main"),
+                map_below,
+                Box::new(sprite_pass),
+                map_above,
+                Box::new(fade_pass),
             ]);
 
-            pipe.layers.push(layer);
+            // the old level's tilemap goes away along with the passes drawing it
+            self.atlas = Some(atlas);
+            self.tilemap_drawer = tilemap_drawer;
+            self.chunked_drawer = chunked_drawer;
+            match self.scene_layer {
+                Some(idx) => pipe.layers[idx] = layer,
+                None => {
+                    self.scene_layer = Some(pipe.layers.len());
+                    pipe.layers.push(layer);
+                },
+            }
         }
 
+        world.add_resource::<CollisionMap>(collision);
+        // edits queued for the old level don't carry over
+        world.add_resource::<tile_edits::TileEdits>(tile_edits::TileEdits::new());
+
         {
-            let dim = world.read_resource::<ScreenDimensions>();
             let mut camera = world.write_resource::<Camera>();
-            let aspect_ratio = dim.aspect_ratio;
-            let eye = [dim.w / 2.0, dim.h / 2.0, 0.1];
-            let target = [dim.w / 2.0, dim.h / 2.0, 0.];
+            let eye = [view_size[0] / 2.0, view_size[1] / 2.0, 0.1];
+            let target = [view_size[0] / 2.0, view_size[1] / 2.0, 0.];
             let up = [0., 1., 0.];
 
-            // Get an Orthographic projection
-            let proj = Projection::Orthographic {
-                left: -dim.w / 2.0,
-                right: dim.w / 2.0,
-                bottom: -dim.h / 2.0,
-                top: dim.h / 2.0,
-                near: 0.0,
-                far: 1.0,
-            };
-
-            camera.proj = proj;
+            camera.proj = orthographic(view_size);
             camera.eye = eye;
             camera.target = target;
             camera.up = up;
+
+            // infinite maps have no edges to keep the camera in
+            let map = &self.level.map;
+            let map_size = if self.chunked_drawer.is_some() {
+                None
+            } else {
+                Some([(map.width * map.tile_width) as f32, (map.height * map.tile_height) as f32])
+            };
+            world.add_resource::<systems::CameraFocus>(systems::CameraFocus::new([eye[0], eye[1]], map_size, view_size));
         }
 
-        assets.register_asset::<Mesh>();
-        assets.register_asset::<Texture>();
+        entities::despawn_map_objects(world);
+        entities::spawn_map_objects(world, &self.level.map, self.atlas.as_ref().unwrap(), &self.sprite_sheets, start);
+        Ok(())
+    }
 
-        assets.load_asset_from_data::<Texture, [f32; 4]>("white", [1.0, 1.0, 1.0, 1.0]);
-        assets.load_asset_from_data::<Mesh, Vec<VertexPosNormal>>("player", entities::Player::get_renderable_verts());
+    // Applies the tile edits systems queued up to the level's map, the tilemap and collision.
+    // Edits to layers the map doesn't have, or outside of it, are dropped. Infinite maps keep
+    // their tiles in chunks and can't be edited yet.
+    fn apply_tile_edits(&mut self, world: &mut World) {
+        let edits = world.write_resource::<tile_edits::TileEdits>().take();
+        if edits.is_empty() || self.chunked_drawer.is_some() {
+            return
+        }
 
-        assets.register_store(DirectoryStore::new("./resources"));
+        let mut collision = world.write_resource::<CollisionMap>();
+        let map = &mut self.level.map;
+        for edit in edits {
+            let layer = match map.layers.iter().position(|layer| layer.name == edit.layer) {
+                Some(layer) => layer,
+                None => continue,
+            };
+            let (xpos, ypos) = (edit.position[0], edit.position[1]);
+            if xpos >= map.width as usize || ypos >= map.height as usize {
+                continue
+            }
+
+            // Tiled's rows go top down
+            let row = map.height as usize - 1 - ypos;
+            match map.layers[layer].tiles.get_mut(row).and_then(|tiles| tiles.get_mut(xpos)) {
+                Some(gid) => *gid = edit.gid,
+                None => continue,
+            }
+            collision.set_tile(layer, xpos, ypos, edit.gid);
+            if let Some(ref tilemap) = self.tilemap_drawer {
+                tilemap.lock().unwrap().set_tile_gid(layer, xpos, ypos, edit.gid);
+            }
+        }
+    }
+
+    // Writes the level as it is now, tile edits included, next to its map as NAME.saved.tmx.
+    // The original is left alone since tiled doesn't keep everything a map can have in it.
+    fn save_level(&self) -> error::Result<()> {
+        // tiled doesn't keep the chunks, what it would write is an empty finite map
+        if self.chunked_drawer.is_some() || self.level.infinite_layers.is_some() {
+            return Err(Error::unsupported(&self.level.path, "saving an infinite map".to_string()))
+        }
+        let path = self.level.path.with_extension(tmx::SAVED_EXTENSION);
+        let file = try!(std::fs::File::create(&path).map_err(|e| Error::io(&path, e)));
+        tmx::write_map(&self.level.map, &self.level.layout, tmx::DataEncoding::Csv, std::io::BufWriter::new(file))
+            .map_err(|e| Error::io(&path, e))
+    }
+
+    // Rebuilds the level from whatever's in resources/ now, keeping the player and the camera
+    // where they were. config.yml is only read when the window gets created, changes to it
+    // still need a restart.
+    fn reload(&mut self, world: &mut World, assets: &mut AssetManager, pipe: &mut Pipeline) -> error::Result<()> {
+        use amethyst::ecs::resources::Camera;
+
+        let sprite_sheets = try!(load_sprite_sheets());
+        let level = try!(Level::load(&self.level.path));
+
+        let player = world.read::<entities::Player>().iter().next().map(|player| player.position);
+        let focus = world.read_resource::<systems::CameraFocus>().position;
+        let start = match player {
+            Some(position) => entities::PlayerStart::Position(position),
+            None => entities::PlayerStart::Spawn(None),
+        };
+
+        let old_sheets = std::mem::replace(&mut self.sprite_sheets, sprite_sheets);
+        if let Err(err) = self.switch_level(world, assets, pipe, level, &start) {
+            self.sprite_sheets = old_sheets;
+            return Err(err)
+        }
+
+        // the new tilemap starts out under the default camera, scrolling it back has it catch up
+        let mut camera_focus = world.write_resource::<systems::CameraFocus>();
+        camera_focus.scrolled = [focus[0] - camera_focus.position[0], focus[1] - camera_focus.position[1]];
+        camera_focus.position = focus;
+        let mut camera = world.write_resource::<Camera>();
+        camera.eye = [focus[0], focus[1], 0.1];
+        camera.target = [focus[0], focus[1], 0.0];
+        Ok(())
+    }
+
+    // Enters level in place of the current one. enter_level doesn't swap anything in until it
+    // can no longer fail, so on an error putting the old level back keeps it running as it was.
+    fn switch_level(&mut self, world: &mut World, assets: &mut AssetManager, pipe: &mut Pipeline, level: Level, start: &entities::PlayerStart) -> error::Result<()> {
+        let old_level = std::mem::replace(&mut self.level, level);
+        if let Err(err) = self.enter_level(world, assets, pipe, start) {
+            self.level = old_level;
+            return Err(err)
+        }
+        Ok(())
+    }
+
+    // Moves the fade along and loads the next level once the screen is covered. A door to a map
+    // that fails to load or enter is reported and leaves the player where they are.
+    fn update_transition(&mut self, world: &mut World, assets: &mut AssetManager, pipe: &mut Pipeline, delta: f32) {
+        let entered = world.write_resource::<systems::EnteredPortal>().0.take();
+        if let Some(portal) = entered {
+            // portals touched while already on the way somewhere are ignored
+            if self.transition.is_none() {
+                self.transition = Some(level::Transition::new(portal.target, portal.fade));
+            }
+        }
+
+        let arrived = match self.transition {
+            Some(ref mut transition) => transition.advance(delta),
+            None => None,
+        };
+        if let Some(target) = arrived {
+            let start = entities::PlayerStart::Spawn(target.spawn);
+            let entered = Level::load(&self.level.resolve(&target.map))
+                .and_then(|level| self.switch_level(world, assets, pipe, level, &start));
+            if let Err(err) = entered {
+                report(&err);
+            }
+        }
+
+        let opacity = match self.transition {
+            Some(ref transition) => transition.opacity(),
+            None => 0.0,
+        };
+        *self.fade.lock().unwrap() = opacity;
+        if self.transition.as_ref().map_or(false, |transition| transition.finished()) {
+            self.transition = None;
+        }
+    }
+}
+
+impl State for Game {
+    fn on_start(&mut self, world: &mut World, assets: &mut AssetManager, pipe: &mut Pipeline) {
+        if let Err(err) = self.start(world, assets, pipe) {
+            self.start_error = Some(err);
+        }
+    }
+
+    fn update(&mut self, world: &mut World, assets: &mut AssetManager, pipe: &mut Pipeline) -> Trans {
+        use amethyst::ecs::components::LocalTransform;
+        use amethyst::ecs::resources::Time;
+        use amethyst::renderer::target::ColorBuffer;
+        use rendering::Sprite;
+
+        if let Some(err) = self.start_error.take() {
+            report(&err);
+            return Trans::Quit
+        }
+
+        let delta = systems::duration_secs(world.read_resource::<Time>().delta_time);
+        self.update_transition(world, assets, pipe, delta);
+
+        // saved files are picked up between levels, not half way through changing them
+        let saved = match (self.watcher.as_mut(), self.transition.is_none()) {
+            (Some(watcher), true) => !watcher.poll(delta).is_empty(),
+            _ => false,
+        };
+        if saved {
+            // a half finished edit shouldn't end the game, it's reported and the old level kept
+            if let Err(err) = self.reload(world, assets, pipe) {
+                report(&err);
+            }
+        }
+
+        self.apply_tile_edits(world);
 
-        let square = assets.create_renderable("player", "white", "white", "white", 1.0).unwrap();
+        let scrolled = world.write_resource::<systems::CameraFocus>().take_scrolled();
 
-        let player = entities::Player::new();
+        if let Some(ref tilemap) = self.tilemap_drawer {
+            let mut tilemap = tilemap.lock().unwrap();
+            tilemap.update(delta);
+            // keep the uploaded window of tiles under the camera
+            tilemap.apply_x_offset(scrolled[0]);
+            tilemap.apply_y_offset(scrolled[1]);
+        }
+
+        if let Some(chunked) = self.chunked_drawer.clone() {
+            let factory = assets.get_loader_mut::<gfx_types::Factory>()
+                .expect("Couldn't retrieve factory.");
+            let scene_target = pipe.targets.get(self.scene_target()).unwrap() as &Box<amethyst::renderer::Target>;
+            let scene_target = scene_target.downcast_ref::<ColorBuffer<gfx_types::Resources>>().unwrap();
 
-        world.create_now()
-            .with(square.clone())
-            .with(player)
-            .with(LocalTransform::default())
-            .with(Transform::default())
-            .build();
+            if let Err(err) = chunked.lock().unwrap().update_view(Game::view_rect(world), factory, &scene_target) {
+                report(&err);
+                return Trans::Quit
+            }
+        }
+
+        {
+            let sprites = world.read::<Sprite>();
+            let locals = world.read::<LocalTransform>();
+            let placed = (&sprites, &locals).iter()
+                .map(|(sprite, local)| (*sprite, [local.translation[0], local.translation[1]]))
+                .collect();
+            self.sprite_batch.lock().unwrap().build(placed);
+        }
+        Trans::None
     }
 
-    fn handle_events(&mut self, events: &[WindowEvent], world: &mut World, _: &mut AssetManager, _: &mut Pipeline) -> Trans {
+    fn handle_events(&mut self, events: &[WindowEvent], world: &mut World, assets: &mut AssetManager, pipe: &mut Pipeline) -> Trans {
         use amethyst::ecs::resources::InputHandler;
 
-        let mut input = world.write_resource::<InputHandler>();
-        input.update(events);
+        world.write_resource::<InputHandler>().update(events);
 
         for e in events {
             match **e {
                 Event::KeyboardInput(_, _, Some(VirtualKeyCode::Escape)) => return Trans::Quit,
+                Event::KeyboardInput(ElementState::Pressed, _, Some(VirtualKeyCode::F5)) => {
+                    // saving is allowed to fail, the game carries on either way
+                    if let Err(err) = self.save_level() {
+                        report(&err);
+                    }
+                },
                 Event::Closed => return Trans::Quit,
+                Event::Resized(w, h) => {
+                    // the old size keeps working, so a failed resize isn't worth quitting over
+                    if let Err(err) = self.resize(world, assets, pipe, [w, h]) {
+                        report(&err);
+                    }
+                },
                 _ => (),
             }
         }
@@ -122,16 +540,74 @@ impl State for Game {
     }
 }
 
-fn main() {
-    let path = "./resources/config.yml";
-    let cfg = DisplayConfig::from_file(path).unwrap();
+// Reads everything the game needs from resources/ that doesn't need the gpu
+fn load_game() -> error::Result<(Game, DisplayConfig)> {
+    let path = Path::new("./resources/config.yml");
+    let cfg = try!(DisplayConfig::from_file(path).map_err(|e| Error::parse(path, format!("{:?}", e))));
+
+    let level = try!(Level::load(Path::new("./resources/map.tmx")));
+
+    let sprite_sheets = try!(load_sprite_sheets());
+
+    // --pixel-perfect=480x270 draws at that resolution and scales it up by a whole number
+    let pixel_perfect = match std::env::args().find(|arg| arg.starts_with("--pixel-perfect=")) {
+        Some(arg) => {
+            let resolution = &arg["--pixel-perfect=".len()..];
+            let config = try!(PixelPerfectConfig::parse(resolution)
+                .ok_or_else(|| Error::Args(format!("--pixel-perfect expects WIDTHxHEIGHT, got {:?}", resolution))));
+            Some(config)
+        },
+        None => None,
+    };
 
-    let map_file = File::open(&Path::new("./resources/map.tmx")).unwrap();
-    let map = parse(map_file).unwrap();
+    // --hot-reload rebuilds the level whenever a file in resources/ is saved, for working on maps
+    let watcher = if std::env::args().any(|arg| arg == "--hot-reload") {
+        Some(hot_reload::ResourceWatcher::new(Path::new("./resources")))
+    } else {
+        None
+    };
+
+    let game = Game{
+        level: level,
+        tilemap_drawer: None,
+        chunked_drawer: None,
+        pixel_perfect: pixel_perfect,
+        upscale_source: None,
+        sprite_sheets: sprite_sheets,
+        atlas: None,
+        sprite_batch: rendering::shared(SpriteBatch::new()),
+        scene_layer: None,
+        transition: None,
+        fade: rendering::shared(0.0),
+        watcher: watcher,
+        start_error: None,
+    };
+    Ok((game, cfg))
+}
+
+fn main() {
+    let (game, cfg) = match load_game() {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            report(&err);
+            std::process::exit(1);
+        },
+    };
 
-    let game = Game{ map: map, cfg: cfg.clone(), tilemap_drawer: None, };
     let mut app = Application::build(game, cfg)
         .register::<entities::Player>()
+        .register::<entities::Enemy>()
+        .register::<entities::Trigger>()
+        .register::<entities::Portal>()
+        .register::<entities::MapProperties>()
+        .register::<collision::Contacts>()
+        .register::<rendering::Sprite>()
+        .register::<rendering::SpriteAnimation>()
+        .with::<systems::PlayerMovement>(systems::PlayerMovement::new(systems::MovementConfig::default()), "player_movement", 2)
+        .with::<systems::PlayerAnimation>(systems::PlayerAnimation, "player_animation", 1)
+        .with::<systems::SpriteAnimator>(systems::SpriteAnimator, "sprite_animator", 0)
+        .with::<systems::EnterPortals>(systems::EnterPortals::new(), "enter_portals", 1)
+        .with::<systems::CameraFollow>(systems::CameraFollow::new(systems::CameraConfig::default()), "camera_follow", 1)
         .done();
 
     app.run();
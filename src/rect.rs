@@ -2,36 +2,73 @@ extern crate amethyst;
 
 use amethyst::renderer::{VertexPosNormal};
 
-pub fn gen_rectangle(w: f32, h: f32) -> Vec<VertexPosNormal> {
+// A w by h quad around the origin showing the [left, top, right, bottom] area of a texture,
+// eg a sprite sheet frame or an atlas region
+pub fn gen_rectangle_uv(w: f32, h: f32, uv: [f32; 4]) -> Vec<VertexPosNormal> {
+    let (left, top, right, bottom) = (uv[0], uv[1], uv[2], uv[3]);
     let data: Vec<VertexPosNormal> = vec![VertexPosNormal {
                                               pos: [-w / 2., -h / 2., 0.],
                                               normal: [0., 0., 1.],
-                                              tex_coord: [0., 0.],
+                                              tex_coord: [left, bottom],
                                           },
                                           VertexPosNormal {
                                               pos: [w / 2., -h / 2., 0.],
                                               normal: [0., 0., 1.],
-                                              tex_coord: [1., 0.],
+                                              tex_coord: [right, bottom],
                                           },
                                           VertexPosNormal {
                                               pos: [w / 2., h / 2., 0.],
                                               normal: [0., 0., 1.],
-                                              tex_coord: [1., 1.],
+                                              tex_coord: [right, top],
                                           },
                                           VertexPosNormal {
                                               pos: [w / 2., h / 2., 0.],
                                               normal: [0., 0., 1.],
-                                              tex_coord: [1., 1.],
+                                              tex_coord: [right, top],
                                           },
                                           VertexPosNormal {
                                               pos: [-w / 2., h / 2., 0.],
                                               normal: [0., 0., 1.],
-                                              tex_coord: [0., 1.],
+                                              tex_coord: [left, top],
                                           },
                                           VertexPosNormal {
                                               pos: [-w / 2., -h / 2., 0.],
                                               normal: [0., 0., 1.],
-                                              tex_coord: [0., 0.],
+                                              tex_coord: [left, bottom],
                                           }];
     data
 }
+
+// Axis aligned rectangle in world space, x/y is the bottom left corner with y going up
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+impl Rect {
+    pub fn new(x: f32, y: f32, w: f32, h: f32) -> Rect {
+        Rect { x: x, y: y, w: w, h: h }
+    }
+
+    // builds a rect around a centre point, the way entity positions are stored
+    pub fn from_center(center: [f32; 2], w: f32, h: f32) -> Rect {
+        Rect::new(center[0] - w / 2.0, center[1] - h / 2.0, w, h)
+    }
+
+    pub fn right(&self) -> f32 {
+        self.x + self.w
+    }
+
+    pub fn top(&self) -> f32 {
+        self.y + self.h
+    }
+
+    // touching edges don't count as overlapping
+    pub fn overlaps(&self, other: &Rect) -> bool {
+        self.x < other.right() && self.right() > other.x &&
+            self.y < other.top() && self.top() > other.y
+    }
+}
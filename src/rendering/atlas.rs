@@ -0,0 +1,192 @@
+extern crate gfx;
+extern crate amethyst;
+extern crate image;
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use amethyst::gfx_device::gfx_types;
+use image::GenericImage;
+
+use error::{Error, Result};
+use rendering::TilesheetView;
+
+// biggest page the atlas builder will make, images are packed into as few of these as they fit
+pub const ATLAS_PAGE_SIZE: [u32; 2] = [2048, 2048];
+// plain white image always packed into the atlas, for untextured boxes
+pub const WHITE_IMAGE: &'static str = "white.png";
+// transparent pixels kept around each image so neighbours don't bleed into each other
+pub const ATLAS_PADDING: u32 = 2;
+
+// Shelf packs rects of the given sizes into pages of page_size, tallest first. Returns the page
+// and top left corner of each rect, in the order they were given.
+pub fn pack_rects(sizes: &[[u32; 2]], page_size: [u32; 2], padding: u32) -> ::std::result::Result<Vec<(usize, [u32; 2])>, String> {
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by(|&a, &b| sizes[b][1].cmp(&sizes[a][1]));
+
+    let mut placed = vec![(0, [0, 0]); sizes.len()];
+    let mut page = 0;
+    let mut x = padding;
+    let mut y = padding;
+    let mut shelf_height = 0;
+    for idx in order {
+        let size = sizes[idx];
+        if size[0] + padding * 2 > page_size[0] || size[1] + padding * 2 > page_size[1] {
+            return Err(format!("a {}x{} image doesn't fit in a {}x{} atlas page", size[0], size[1], page_size[0], page_size[1]));
+        }
+        // start a new shelf when the image doesn't fit on this one, and a new page when out of shelves
+        if x + size[0] + padding > page_size[0] {
+            x = padding;
+            y += shelf_height + padding;
+            shelf_height = 0;
+        }
+        if y + size[1] + padding > page_size[1] {
+            page += 1;
+            x = padding;
+            y = padding;
+            shelf_height = 0;
+        }
+        placed[idx] = (page, [x, y]);
+        x += size[0] + padding;
+        shelf_height = shelf_height.max(size[1]);
+    }
+    Ok(placed)
+}
+
+// Where an image ended up in the atlas
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AtlasRegion {
+    pub page: usize,
+    // x, y, w, h in page pixels, y going down
+    pub rect: [u32; 4],
+    // [left, top, right, bottom] in the page's texture space
+    pub uv: [f32; 4],
+}
+
+impl AtlasRegion {
+    // maps uv from the original image's texture space into the page's
+    pub fn sub_uv(&self, uv: [f32; 4]) -> [f32; 4] {
+        let w = self.uv[2] - self.uv[0];
+        let h = self.uv[3] - self.uv[1];
+        [self.uv[0] + uv[0] * w, self.uv[1] + uv[1] * h, self.uv[0] + uv[2] * w, self.uv[1] + uv[3] * h]
+    }
+}
+
+pub struct AtlasPage {
+    pub view: TilesheetView,
+    pub size: [u32; 2],
+}
+
+// Several images packed into a few large textures, looked up by the name they were loaded with
+pub struct TextureAtlas {
+    pub pages: Vec<AtlasPage>,
+    regions: HashMap<String, AtlasRegion>,
+}
+
+impl TextureAtlas {
+    // Loads the named images from dir and packs them, names being paths relative to dir
+    pub fn build<F>(factory: &mut F, dir: &Path, names: &[String]) -> Result<TextureAtlas>
+        where F: gfx::Factory<gfx_types::Resources>
+    {
+        let mut unique: Vec<&String> = Vec::new();
+        for name in names.iter() {
+            if !unique.contains(&name) {
+                unique.push(name);
+            }
+        }
+
+        let mut images = Vec::with_capacity(unique.len());
+        let mut sizes: Vec<[u32; 2]> = Vec::with_capacity(unique.len());
+        for name in unique.iter() {
+            let path = dir.join(name);
+            let img = try!(image::open(&path).map_err(|e| Error::Image(path.clone(), e))).to_rgba();
+            let (w, h) = img.dimensions();
+            images.push(img);
+            sizes.push([w, h]);
+        }
+
+        let placed = try!(pack_rects(&sizes, ATLAS_PAGE_SIZE, ATLAS_PADDING).map_err(Error::Asset));
+
+        // pages only get as big as what ended up on them
+        let page_count = placed.iter().map(|&(page, _)| page + 1).max().unwrap_or(0);
+        let mut page_sizes = vec![[0, 0]; page_count];
+        for (&(page, pos), size) in placed.iter().zip(sizes.iter()) {
+            page_sizes[page][0] = page_sizes[page][0].max(pos[0] + size[0] + ATLAS_PADDING);
+            page_sizes[page][1] = page_sizes[page][1].max(pos[1] + size[1] + ATLAS_PADDING);
+        }
+
+        let mut page_pixels: Vec<image::RgbaImage> = page_sizes.iter()
+            .map(|size| image::ImageBuffer::new(size[0], size[1]))
+            .collect();
+        let mut regions = HashMap::new();
+        for ((name, img), &(page, pos)) in unique.iter().zip(images.iter()).zip(placed.iter()) {
+            page_pixels[page].copy_from(img, pos[0], pos[1]);
+
+            let (w, h) = img.dimensions();
+            let page_w = page_sizes[page][0] as f32;
+            let page_h = page_sizes[page][1] as f32;
+            regions.insert(name.to_string(), AtlasRegion {
+                page: page,
+                rect: [pos[0], pos[1], w, h],
+                uv: [
+                    pos[0] as f32 / page_w,
+                    pos[1] as f32 / page_h,
+                    (pos[0] + w) as f32 / page_w,
+                    (pos[1] + h) as f32 / page_h,
+                ],
+            });
+        }
+
+        let mut pages = Vec::with_capacity(page_count);
+        for (pixels, size) in page_pixels.into_iter().zip(page_sizes.into_iter()) {
+            let kind = gfx::texture::Kind::D2(size[0] as u16, size[1] as u16, gfx::texture::AaMode::Single);
+            let (_, view) = try!(factory.create_texture_immutable_u8::<gfx::format::Rgba8>(kind, &[&pixels])
+                .map_err(|e| Error::gpu("atlas page texture", e)));
+            pages.push(AtlasPage {
+                view: view,
+                size: size,
+            });
+        }
+
+        Ok(TextureAtlas {
+            pages: pages,
+            regions: regions,
+        })
+    }
+
+    pub fn region(&self, name: &str) -> Option<&AtlasRegion> {
+        self.regions.get(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packs_onto_shelves() {
+        let placed = pack_rects(&[[10, 10], [10, 20], [10, 10]], [32, 64], 1).unwrap();
+        // the tallest goes first, the rest follow on the same shelf until it's full
+        assert_eq!(placed[1], (0, [1, 1]));
+        assert_eq!(placed[0], (0, [12, 1]));
+        assert_eq!(placed[2], (0, [1, 22]));
+    }
+
+    #[test]
+    fn overflows_onto_new_page() {
+        let placed = pack_rects(&[[30, 30], [30, 30]], [32, 32], 1).unwrap();
+        assert_eq!(placed, vec![(0, [1, 1]), (1, [1, 1])]);
+    }
+
+    #[test]
+    fn rejects_images_bigger_than_a_page() {
+        assert!(pack_rects(&[[40, 10]], [32, 32], 1).is_err());
+    }
+
+    #[test]
+    fn sub_uv_maps_into_region() {
+        let region = AtlasRegion { page: 0, rect: [0, 0, 0, 0], uv: [0.5, 0.0, 1.0, 0.5] };
+        assert_eq!(region.sub_uv([0.0, 0.0, 1.0, 1.0]), [0.5, 0.0, 1.0, 0.5]);
+        assert_eq!(region.sub_uv([0.5, 0.5, 1.0, 1.0]), [0.75, 0.25, 1.0, 0.5]);
+    }
+}
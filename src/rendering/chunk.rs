@@ -0,0 +1,252 @@
+extern crate gfx;
+extern crate amethyst;
+extern crate tiled;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::{Debug, Formatter};
+
+use amethyst::renderer::pass::{DrawFlat, Pass};
+use amethyst::renderer::pass::PassDescription;
+use amethyst::renderer::{Pipeline, Scene};
+use amethyst::renderer::target::GeometryBuffer;
+use amethyst::gfx_device::gfx_types;
+
+use gfx::traits::FactoryExt;
+
+use error::{Error, Result};
+use rect::Rect;
+use rendering::{load_tilesheets, pipe, CBTarget, Shared, TextureAtlas, TileMapData, TileMapPlane, Tilesheet};
+use tmx::{Chunk, InfiniteLayer};
+
+// chunks within this many chunk widths of the view get loaded ahead of time
+const LOAD_MARGIN: f32 = 1.0;
+// and are only evicted once they're further out than this, so the camera hovering over a
+// chunk border doesn't load and drop the same chunks every frame
+const EVICT_MARGIN: f32 = 3.0;
+
+struct LoadedChunk {
+    bounds: Rect,
+    plane: TileMapPlane,
+}
+
+// Tilemap for Tiled's infinite maps. The chunks of every layer stay on the cpu, and the ones
+// near the camera get their own gpu plane, positioned in map pixels with y going up.
+pub struct ChunkedTileMap {
+    map: tiled::Map,
+    layers: Vec<InfiniteLayer>,
+    tilesheets: Vec<Tilesheet>,
+    tile_size: [f32; 2],
+    // keyed by layer index and the chunk's tile coordinates
+    loaded: HashMap<(usize, i32, i32), LoadedChunk>,
+    view: Rect,
+    pso: gfx::PipelineState<gfx_types::Resources, pipe::Meta>,
+}
+
+impl ChunkedTileMap {
+    pub fn new<F>(map: &tiled::Map, layers: Vec<InfiniteLayer>, atlas: &TextureAtlas, factory: &mut F) -> Result<ChunkedTileMap>
+        where F: gfx::Factory<gfx_types::Resources>
+    {
+        let pso = try!(factory.create_pipeline_simple(
+            include_bytes!("shader/tilemap_150.glslv"),
+            include_bytes!("shader/tilemap_150.glslf"),
+            pipe::new()
+        ).map_err(|e| Error::gpu("tilemap pipeline", e)));
+
+        Ok(ChunkedTileMap {
+            map: map.clone(),
+            layers: layers,
+            tilesheets: try!(load_tilesheets(atlas, map)),
+            tile_size: [map.tile_width as f32, map.tile_height as f32],
+            loaded: HashMap::new(),
+            view: Rect::new(0.0, 0.0, 0.0, 0.0),
+            pso: pso,
+        })
+    }
+
+    fn chunk_bounds(&self, chunk: &Chunk) -> Rect {
+        chunk_bounds(chunk, self.map.height, self.tile_size)
+    }
+
+    fn build_chunk<F>(&self, chunk: &Chunk, opacity: f32, factory: &mut F, target: &CBTarget) -> Result<LoadedChunk>
+        where F: gfx::Factory<gfx_types::Resources>
+    {
+        let bounds = self.chunk_bounds(chunk);
+        let mut plane = try!(TileMapPlane::new(
+            factory, &self.map, [chunk.width, chunk.height], &self.tilesheets, opacity, target
+        ));
+        for row in 0..chunk.height {
+            let ypos = chunk.height - 1 - row;
+            for xpos in 0..chunk.width {
+                let gid = chunk.gids[(row * chunk.width) + xpos];
+                plane.data[(ypos * chunk.width) + xpos] = TileMapData::from_gid(&self.map.tilesets, gid);
+            }
+        }
+        plane.mark_all_dirty();
+        // the plane is built around the origin
+        plane.set_translation([bounds.x + bounds.w / 2.0, bounds.y + bounds.h / 2.0, 0.0]);
+
+        Ok(LoadedChunk {
+            bounds: bounds,
+            plane: plane,
+        })
+    }
+
+    // Loads the chunks around view, the visible area in map pixels, and drops the far away ones
+    pub fn update_view<F>(&mut self, view: Rect, factory: &mut F, target: &CBTarget) -> Result<()>
+        where F: gfx::Factory<gfx_types::Resources>
+    {
+        self.view = view;
+
+        let mut to_load = Vec::new();
+        for (layer_idx, layer) in self.layers.iter().enumerate() {
+            for chunk in layer.chunks.iter() {
+                let key = (layer_idx, chunk.x, chunk.y);
+                if !self.loaded.contains_key(&key) && should_load(&view, &self.chunk_bounds(chunk)) {
+                    to_load.push(key);
+                }
+            }
+        }
+
+        for (layer_idx, x, y) in to_load {
+            let loaded = {
+                let layer = &self.layers[layer_idx];
+                let chunk = layer.chunks.iter().find(|chunk| chunk.x == x && chunk.y == y).unwrap();
+                try!(self.build_chunk(chunk, layer.opacity, factory, target))
+            };
+            self.loaded.insert((layer_idx, x, y), loaded);
+        }
+
+        let evict: Vec<(usize, i32, i32)> = self.loaded.iter()
+            .filter(|&(_, chunk)| should_evict(&view, &chunk.bounds))
+            .map(|(key, _)| *key)
+            .collect();
+        for key in evict {
+            self.loaded.remove(&key);
+        }
+        Ok(())
+    }
+}
+
+// Where a chunk is in map pixels. Tiled's chunk rows go down from the top of the map, they're
+// flipped the same way as finite maps so objects and chunks agree on where things are
+pub fn chunk_bounds(chunk: &Chunk, map_height: u32, tile_size: [f32; 2]) -> Rect {
+    Rect::new(
+        chunk.x as f32 * tile_size[0],
+        chunk.bottom_row(map_height) as f32 * tile_size[1],
+        chunk.width as f32 * tile_size[0],
+        chunk.height as f32 * tile_size[1],
+    )
+}
+
+fn expand(rect: &Rect, x: f32, y: f32) -> Rect {
+    Rect::new(rect.x - x, rect.y - y, rect.w + x * 2.0, rect.h + y * 2.0)
+}
+
+// whether a chunk that isn't loaded is close enough to view to load it
+fn should_load(view: &Rect, bounds: &Rect) -> bool {
+    bounds.overlaps(&expand(view, bounds.w * LOAD_MARGIN, bounds.h * LOAD_MARGIN))
+}
+
+// whether a loaded chunk has got far enough from view to drop it
+fn should_evict(view: &Rect, bounds: &Rect) -> bool {
+    !bounds.overlaps(&expand(view, bounds.w * EVICT_MARGIN, bounds.h * EVICT_MARGIN))
+}
+
+pub struct ChunkDrawPass {
+    tilemap: Shared<ChunkedTileMap>,
+    // indices of the layers this pass draws, back to front
+    layers: Vec<usize>,
+}
+
+impl Debug for ChunkDrawPass {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "ChunkDrawPass")
+    }
+}
+
+impl PassDescription for ChunkDrawPass {}
+
+impl ChunkDrawPass {
+    pub fn new(tilemap: Shared<ChunkedTileMap>, layers: Vec<usize>) -> ChunkDrawPass {
+        ChunkDrawPass {
+            tilemap: tilemap,
+            layers: layers,
+        }
+    }
+}
+
+impl Pass<gfx_types::Resources> for ChunkDrawPass {
+    type Arg = DrawFlat;
+    type Target = GeometryBuffer<gfx_types::Resources>;
+
+    fn apply<C>(&self,
+        _: &DrawFlat,
+        _: &GeometryBuffer<gfx_types::Resources>,
+        _: &Pipeline,
+        scene: &Scene<gfx_types::Resources>,
+        encoder: &mut gfx::Encoder<gfx_types::Resources, C>)
+    where C: gfx::CommandBuffer<gfx_types::Resources>
+    {
+        let tilemap = self.tilemap.lock().unwrap();
+
+        // same as the regular tilemap, the pass's layers in order with culling of chunks out of view
+        for &layer_idx in self.layers.iter() {
+            let layer = match tilemap.layers.get(layer_idx) {
+                Some(layer) => layer,
+                None => continue,
+            };
+            if !layer.visible {
+                continue
+            }
+            for chunk in layer.chunks.iter() {
+                if let Some(loaded) = tilemap.loaded.get(&(layer_idx, chunk.x, chunk.y)) {
+                    if !loaded.bounds.overlaps(&tilemap.view) {
+                        continue
+                    }
+                    loaded.plane.prepare_buffers(encoder, scene.camera.proj, scene.camera.view);
+                    encoder.draw(&loaded.plane.slice, &tilemap.pso, &loaded.plane.params);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use tmx::read_infinite_layers;
+
+    #[test]
+    fn chunk_bounds_are_in_map_pixels_with_y_up() {
+        let layers = read_infinite_layers(File::open("./tests/fixtures/infinite.tmx").unwrap()).unwrap().unwrap();
+        let bounds = chunk_bounds(&layers[0].chunks[0], 8, [32.0, 32.0]);
+        assert_eq!((bounds.x, bounds.y, bounds.w, bounds.h), (-128.0, 128.0, 128.0, 128.0));
+        let bounds = chunk_bounds(&layers[0].chunks[1], 8, [32.0, 32.0]);
+        assert_eq!((bounds.x, bounds.y, bounds.w, bounds.h), (0.0, 0.0, 128.0, 128.0));
+    }
+
+    #[test]
+    fn loads_chunks_in_and_next_to_the_view() {
+        let view = Rect::new(0.0, 0.0, 256.0, 256.0);
+        assert!(should_load(&view, &Rect::new(128.0, 128.0, 128.0, 128.0)));
+        // within a chunk of the view
+        assert!(should_load(&view, &Rect::new(300.0, 0.0, 128.0, 128.0)));
+        assert!(should_load(&view, &Rect::new(-200.0, -200.0, 128.0, 128.0)));
+        assert!(!should_load(&view, &Rect::new(600.0, 0.0, 128.0, 128.0)));
+    }
+
+    #[test]
+    fn keeps_chunks_until_well_past_the_load_margin() {
+        let view = Rect::new(0.0, 0.0, 256.0, 256.0);
+        // too far to load but not far enough to drop
+        let nearby = Rect::new(600.0, 0.0, 128.0, 128.0);
+        assert!(!should_load(&view, &nearby));
+        assert!(!should_evict(&view, &nearby));
+
+        assert!(should_evict(&view, &Rect::new(700.0, 0.0, 128.0, 128.0)));
+        assert!(should_evict(&view, &Rect::new(0.0, -700.0, 128.0, 128.0)));
+        assert!(!should_evict(&view, &Rect::new(128.0, 128.0, 128.0, 128.0)));
+    }
+}
@@ -0,0 +1,97 @@
+use std::mem;
+
+// the most ranges kept apart, past this the two closest ones get merged
+const MAX_RANGES: usize = 8;
+
+// Parts of a gpu buffer waiting to be uploaded, as sorted ranges that don't touch, so changing
+// a few tiles far apart uploads just those and not everything in between
+pub struct DirtyRanges {
+    ranges: Vec<(usize, usize)>,
+}
+
+impl DirtyRanges {
+    pub fn new() -> DirtyRanges {
+        DirtyRanges {
+            ranges: Vec::new(),
+        }
+    }
+
+    // Adds start..end, merging it with the ranges it overlaps or is next to
+    pub fn add(&mut self, start: usize, end: usize) {
+        if start >= end {
+            return
+        }
+        let mut merged = (start, end);
+        let mut ranges = Vec::with_capacity(self.ranges.len() + 1);
+        for &(range_start, range_end) in self.ranges.iter() {
+            if range_end < merged.0 || range_start > merged.1 {
+                ranges.push((range_start, range_end));
+            } else {
+                merged = (merged.0.min(range_start), merged.1.max(range_end));
+            }
+        }
+        ranges.push(merged);
+        ranges.sort();
+
+        // lots of scattered changes cost more in update calls than the few entries between them
+        while ranges.len() > MAX_RANGES {
+            let closest = (0..ranges.len() - 1).min_by_key(|&idx| ranges[idx + 1].0 - ranges[idx].1).unwrap();
+            ranges[closest].1 = ranges[closest + 1].1;
+            ranges.remove(closest + 1);
+        }
+        self.ranges = ranges;
+    }
+
+    // the ranges to upload, leaving nothing dirty
+    pub fn take(&mut self) -> Vec<(usize, usize)> {
+        mem::replace(&mut self.ranges, Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_far_apart_changes_separate() {
+        let mut dirty = DirtyRanges::new();
+        dirty.add(2000, 2001);
+        dirty.add(3, 4);
+        assert_eq!(dirty.take(), vec![(3, 4), (2000, 2001)]);
+        assert!(dirty.take().is_empty());
+    }
+
+    #[test]
+    fn merges_overlapping_and_adjacent_ranges() {
+        let mut dirty = DirtyRanges::new();
+        dirty.add(10, 11);
+        dirty.add(11, 12);
+        dirty.add(20, 30);
+        dirty.add(25, 40);
+        dirty.add(5, 6);
+        assert_eq!(dirty.take(), vec![(5, 6), (10, 12), (20, 40)]);
+
+        dirty.add(10, 11);
+        dirty.add(0, 100);
+        assert_eq!(dirty.take(), vec![(0, 100)]);
+    }
+
+    #[test]
+    fn merges_the_closest_ranges_past_the_limit() {
+        let mut dirty = DirtyRanges::new();
+        for idx in 0..MAX_RANGES {
+            dirty.add(idx * 100, idx * 100 + 1);
+        }
+        dirty.add(702, 703);
+        let ranges = dirty.take();
+        assert_eq!(ranges.len(), MAX_RANGES);
+        assert_eq!(ranges[MAX_RANGES - 1], (700, 703));
+    }
+
+    #[test]
+    fn ignores_empty_ranges() {
+        let mut dirty = DirtyRanges::new();
+        dirty.add(5, 5);
+        assert!(dirty.take().is_empty());
+    }
+}
@@ -0,0 +1,106 @@
+extern crate gfx;
+extern crate amethyst;
+
+use std::fmt;
+use std::fmt::{Debug, Formatter};
+
+use amethyst::renderer::pass::{DrawFlat, Pass};
+use amethyst::renderer::pass::PassDescription;
+use amethyst::renderer::{Pipeline, Scene};
+use amethyst::renderer::target::GeometryBuffer;
+use amethyst::gfx_device::gfx_types;
+
+use gfx::traits::FactoryExt;
+
+use error::{Error, Result};
+use rendering::{CBTarget, Shared};
+
+gfx_defines!{
+    vertex FadeVertex {
+        pos: [f32; 2] = "a_Pos",
+    }
+
+    constant FadeLocals {
+        color: [f32; 4] = "u_Color",
+    }
+
+    pipeline fade_pipe {
+        vbuf: gfx::VertexBuffer<FadeVertex> = (),
+        locals: gfx::ConstantBuffer<FadeLocals> = "b_FadeLocals",
+        out_color: gfx::BlendTarget<gfx::format::Rgba8> =
+            ("Target0", gfx::state::MASK_ALL, gfx::preset::blend::ALPHA),
+    }
+}
+
+// Covers the whole target in a colour, at the opacity the game sets. Used to fade out and
+// back in when changing maps. Nothing is drawn while the opacity is 0.
+pub struct FadePass {
+    params: fade_pipe::Data<gfx_types::Resources>,
+    slice: gfx::Slice<gfx_types::Resources>,
+    pso: gfx::PipelineState<gfx_types::Resources, fade_pipe::Meta>,
+    color: [f32; 3],
+    opacity: Shared<f32>,
+}
+
+impl Debug for FadePass {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "FadePass")
+    }
+}
+
+impl PassDescription for FadePass {}
+
+impl FadePass {
+    pub fn new<F>(factory: &mut F, color: [f32; 3], opacity: Shared<f32>, target: &CBTarget) -> Result<FadePass>
+        where F: gfx::Factory<gfx_types::Resources>
+    {
+        let vertex_data = [
+            FadeVertex { pos: [-1.0, -1.0] },
+            FadeVertex { pos: [1.0, -1.0] },
+            FadeVertex { pos: [1.0, 1.0] },
+            FadeVertex { pos: [-1.0, 1.0] },
+        ];
+        let index_data: [u16; 6] = [0, 1, 2, 2, 3, 0];
+        let (vbuf, slice) = factory.create_vertex_buffer_with_slice(&vertex_data, &index_data[..]);
+
+        let pso = try!(factory.create_pipeline_simple(
+            include_bytes!("shader/fade_150.glslv"),
+            include_bytes!("shader/fade_150.glslf"),
+            fade_pipe::new()
+        ).map_err(|e| Error::gpu("fade pipeline", e)));
+
+        Ok(FadePass {
+            params: fade_pipe::Data {
+                vbuf: vbuf,
+                locals: factory.create_constant_buffer(1),
+                out_color: target.color.clone(),
+            },
+            slice: slice,
+            pso: pso,
+            color: color,
+            opacity: opacity,
+        })
+    }
+}
+
+impl Pass<gfx_types::Resources> for FadePass {
+    type Arg = DrawFlat;
+    type Target = GeometryBuffer<gfx_types::Resources>;
+
+    fn apply<C>(&self,
+        _: &DrawFlat,
+        _: &GeometryBuffer<gfx_types::Resources>,
+        _: &Pipeline,
+        _: &Scene<gfx_types::Resources>,
+        encoder: &mut gfx::Encoder<gfx_types::Resources, C>)
+    where C: gfx::CommandBuffer<gfx_types::Resources>
+    {
+        let opacity = *self.opacity.lock().unwrap();
+        if opacity <= 0.0 {
+            return
+        }
+        let color = [self.color[0], self.color[1], self.color[2], opacity.min(1.0)];
+        encoder.update_constant_buffer(&self.params.locals, &FadeLocals { color: color });
+        encoder.draw(&self.slice, &self.pso, &self.params);
+    }
+}
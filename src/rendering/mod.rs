@@ -1,5 +1,33 @@
 extern crate gfx;
 
+mod atlas;
+mod chunk;
+mod dirty_ranges;
+mod fade;
+mod pixel_perfect;
+mod sprite_animation;
+mod sprite_batch;
 mod tiled;
+mod tileset;
+mod tile_animation;
 
+pub use self::atlas::*;
+pub use self::chunk::*;
+pub use self::dirty_ranges::*;
+pub use self::fade::*;
+pub use self::pixel_perfect::*;
+pub use self::sprite_animation::*;
+pub use self::sprite_batch::*;
 pub use self::tiled::*;
+pub use self::tileset::*;
+pub use self::tile_animation::*;
+
+use std::sync::{Arc, Mutex};
+
+// Renderer state owned by the game and read by its passes every frame, so it can be updated,
+// edited or swapped out while the pipeline is running
+pub type Shared<T> = Arc<Mutex<T>>;
+
+pub fn shared<T>(value: T) -> Shared<T> {
+    Arc::new(Mutex::new(value))
+}
@@ -0,0 +1,194 @@
+extern crate gfx;
+extern crate amethyst;
+
+use std::fmt;
+use std::fmt::{Debug, Formatter};
+
+use amethyst::renderer::pass::{DrawFlat, Pass};
+use amethyst::renderer::pass::PassDescription;
+use amethyst::renderer::{Pipeline, Scene};
+use amethyst::renderer::target::{ColorBuffer, GeometryBuffer};
+use amethyst::gfx_device::gfx_types;
+
+use gfx::traits::FactoryExt;
+
+use error::{Error, Result};
+use rendering::CBTarget;
+
+gfx_defines!{
+    vertex BlitVertex {
+        pos: [f32; 2] = "a_Pos",
+        uv: [f32; 2] = "a_Uv",
+    }
+
+    constant BlitLocals {
+        rect: [f32; 4] = "u_Rect",
+    }
+
+    pipeline blit_pipe {
+        vbuf: gfx::VertexBuffer<BlitVertex> = (),
+        locals: gfx::ConstantBuffer<BlitLocals> = "b_BlitLocals",
+        source: gfx::TextureSampler<[f32; 4]> = "t_Source",
+        out_color: gfx::RenderTarget<gfx::format::Rgba8> = "Target0",
+    }
+}
+
+// Low resolution the game is drawn at before being scaled up to the window
+#[derive(Clone, Copy, Debug)]
+pub struct PixelPerfectConfig {
+    pub virtual_size: [u32; 2],
+}
+
+impl PixelPerfectConfig {
+    // parses a resolution written as WIDTHxHEIGHT, eg 480x270
+    pub fn parse(resolution: &str) -> Option<PixelPerfectConfig> {
+        let mut parts = resolution.split('x');
+        match (parts.next().and_then(|w| w.parse().ok()), parts.next().and_then(|h| h.parse().ok()), parts.next()) {
+            (Some(w), Some(h), None) if w > 0 && h > 0 => Some(PixelPerfectConfig { virtual_size: [w, h] }),
+            _ => None,
+        }
+    }
+}
+
+// Largest whole number the virtual resolution can be multiplied by and still fit the window,
+// along with the centred [x, y, w, h] it ends up at in window pixels. Whatever is left over is letterboxed.
+// A window smaller than the virtual resolution still gets a scale of one, with the image centred
+// and cropped evenly on both sides, so x and y go negative.
+pub fn integer_scale(virtual_size: [u32; 2], window_size: [u32; 2]) -> (u32, [i32; 4]) {
+    let scale_x = window_size[0] / virtual_size[0];
+    let scale_y = window_size[1] / virtual_size[1];
+    let scale = scale_x.min(scale_y).max(1);
+
+    let w = (virtual_size[0] * scale) as i32;
+    let h = (virtual_size[1] * scale) as i32;
+    let x = (window_size[0] as i32 - w) / 2;
+    let y = (window_size[1] as i32 - h) / 2;
+    (scale, [x, y, w, h])
+}
+
+// The offscreen target the map and sprites get drawn to, and the texture to read it back from
+pub fn create_virtual_target<F>(factory: &mut F, config: &PixelPerfectConfig) -> Result<(CBTarget, gfx::handle::ShaderResourceView<gfx_types::Resources, [f32; 4]>)>
+    where F: gfx::Factory<gfx_types::Resources>
+{
+    let w = config.virtual_size[0] as u16;
+    let h = config.virtual_size[1] as u16;
+    let (_, view, color) = try!(factory.create_render_target::<gfx::format::Rgba8>(w, h)
+        .map_err(|e| Error::gpu("virtual render target", e)));
+    let depth = try!(factory.create_depth_stencil_view_only::<gfx::format::DepthStencil>(w, h)
+        .map_err(|e| Error::gpu("virtual depth target", e)));
+
+    Ok((ColorBuffer { color: color, output_depth: depth }, view))
+}
+
+// Draws the virtual target onto the main one, scaled up by a whole number with nearest filtering
+pub struct UpscalePass {
+    params: blit_pipe::Data<gfx_types::Resources>,
+    slice: gfx::Slice<gfx_types::Resources>,
+    pso: gfx::PipelineState<gfx_types::Resources, blit_pipe::Meta>,
+    // where the image goes, in normalized device coordinates
+    rect: [f32; 4],
+}
+
+impl Debug for UpscalePass {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "UpscalePass")
+    }
+}
+
+impl PassDescription for UpscalePass {}
+
+impl UpscalePass {
+    pub fn new<F>(factory: &mut F, source: gfx::handle::ShaderResourceView<gfx_types::Resources, [f32; 4]>,
+        config: PixelPerfectConfig, window_size: [u32; 2], target: &CBTarget) -> Result<UpscalePass>
+        where F: gfx::Factory<gfx_types::Resources>
+    {
+        let vertex_data = [
+            BlitVertex { pos: [0.0, 0.0], uv: [0.0, 0.0] },
+            BlitVertex { pos: [1.0, 0.0], uv: [1.0, 0.0] },
+            BlitVertex { pos: [1.0, 1.0], uv: [1.0, 1.0] },
+            BlitVertex { pos: [0.0, 1.0], uv: [0.0, 1.0] },
+        ];
+        let index_data: [u16; 6] = [0, 1, 2, 2, 3, 0];
+        let (vbuf, slice) = factory.create_vertex_buffer_with_slice(&vertex_data, &index_data[..]);
+
+        let sampler = factory.create_sampler(
+            gfx::texture::SamplerInfo::new(
+                gfx::texture::FilterMethod::Scale,
+                gfx::texture::WrapMode::Clamp
+            )
+        );
+
+        let pso = try!(factory.create_pipeline_simple(
+            include_bytes!("shader/blit_150.glslv"),
+            include_bytes!("shader/blit_150.glslf"),
+            blit_pipe::new()
+        ).map_err(|e| Error::gpu("upscale pipeline", e)));
+
+        let (_, rect) = integer_scale(config.virtual_size, window_size);
+        let to_ndc = |value: i32, size: u32| value as f32 / size as f32 * 2.0;
+
+        Ok(UpscalePass {
+            params: blit_pipe::Data {
+                vbuf: vbuf,
+                locals: factory.create_constant_buffer(1),
+                source: (source, sampler),
+                out_color: target.color.clone(),
+            },
+            slice: slice,
+            pso: pso,
+            rect: [
+                to_ndc(rect[0], window_size[0]) - 1.0,
+                to_ndc(rect[1], window_size[1]) - 1.0,
+                to_ndc(rect[2], window_size[0]),
+                to_ndc(rect[3], window_size[1]),
+            ],
+        })
+    }
+}
+
+impl Pass<gfx_types::Resources> for UpscalePass {
+    type Arg = DrawFlat;
+    type Target = GeometryBuffer<gfx_types::Resources>;
+
+    fn apply<C>(&self,
+        _: &DrawFlat,
+        _: &GeometryBuffer<gfx_types::Resources>,
+        _: &Pipeline,
+        _: &Scene<gfx_types::Resources>,
+        encoder: &mut gfx::Encoder<gfx_types::Resources, C>)
+    where C: gfx::CommandBuffer<gfx_types::Resources>
+    {
+        encoder.update_constant_buffer(&self.params.locals, &BlitLocals { rect: self.rect });
+        encoder.draw(&self.slice, &self.pso, &self.params);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scales_by_whole_numbers_and_letterboxes() {
+        assert_eq!(integer_scale([480, 270], [1920, 1080]), (4, [0, 0, 1920, 1080]));
+        assert_eq!(integer_scale([480, 270], [1280, 720]), (2, [160, 90, 960, 540]));
+        assert_eq!(integer_scale([480, 270], [1920, 1200]), (4, [0, 60, 1920, 1080]));
+    }
+
+    #[test]
+    fn never_scales_below_one() {
+        assert_eq!(integer_scale([480, 270], [480, 270]), (1, [0, 0, 480, 270]));
+    }
+
+    #[test]
+    fn centres_when_the_window_is_smaller() {
+        assert_eq!(integer_scale([480, 270], [320, 240]), (1, [-80, -15, 480, 270]));
+        assert_eq!(integer_scale([480, 270], [960, 200]), (1, [240, -35, 480, 270]));
+    }
+
+    #[test]
+    fn parses_resolution() {
+        assert_eq!(PixelPerfectConfig::parse("480x270").map(|c| c.virtual_size), Some([480, 270]));
+        assert!(PixelPerfectConfig::parse("480").is_none());
+        assert!(PixelPerfectConfig::parse("0x270").is_none());
+    }
+}
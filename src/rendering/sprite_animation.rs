@@ -0,0 +1,225 @@
+extern crate amethyst;
+extern crate xml;
+
+use std::io::Read;
+
+use amethyst::ecs::{Component, VecStorage};
+
+use xml::reader::{EventReader, XmlEvent};
+
+use tmx::{attr, parse_attr};
+
+// A run of frames in a sprite sheet, played first to last
+#[derive(Clone, Debug, PartialEq)]
+pub struct Clip {
+    pub name: String,
+    pub first: usize,
+    pub last: usize,
+    // frames per second
+    pub fps: f32,
+    // clips that don't loop hold their last frame
+    pub looping: bool,
+}
+
+// Which frame of the sheet clip shows after elapsed seconds of playing it
+pub fn clip_frame(clip: &Clip, elapsed: f32) -> usize {
+    let count = clip.last - clip.first + 1;
+    if clip.fps <= 0.0 {
+        return clip.first
+    }
+    let played = (elapsed * clip.fps) as usize;
+    if clip.looping {
+        clip.first + played % count
+    } else {
+        clip.first + played.min(count - 1)
+    }
+}
+
+// An image cut into a grid of equally sized frames, numbered left to right, top to bottom
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpriteSheet {
+    pub name: String,
+    // file name of the image in resources/, also its name in the texture atlas
+    pub image: String,
+    pub columns: usize,
+    pub rows: usize,
+    pub clips: Vec<Clip>,
+}
+
+impl SpriteSheet {
+    pub fn frame_count(&self) -> usize {
+        self.columns * self.rows
+    }
+
+    // [left, top, right, bottom] of frame in texture space, v going down the image
+    pub fn frame_uv(&self, frame: usize) -> [f32; 4] {
+        let column = (frame % self.columns) as f32;
+        let row = (frame / self.columns) as f32;
+        let w = 1.0 / self.columns as f32;
+        let h = 1.0 / self.rows as f32;
+        [column * w, row * h, (column + 1.0) * w, (row + 1.0) * h]
+    }
+
+}
+
+// Reads the <sheet> definitions from an animations file like resources/animations.xml
+pub fn read_sprite_sheets<R: Read>(reader: R) -> Result<Vec<SpriteSheet>, String> {
+    let parser = EventReader::new(reader);
+    let mut sheets = Vec::new();
+    let mut sheet: Option<SpriteSheet> = None;
+
+    for event in parser {
+        match try!(event.map_err(|e| format!("{}", e))) {
+            XmlEvent::StartElement { name, attributes, .. } => {
+                match name.local_name.as_str() {
+                    "sheet" => {
+                        sheet = Some(SpriteSheet {
+                            name: try!(attr(&attributes, "name").ok_or("sheet without a name".to_string())).to_string(),
+                            image: try!(attr(&attributes, "image").ok_or("sheet without an image".to_string())).to_string(),
+                            columns: try!(parse_attr(&attributes, "columns", 1)),
+                            rows: try!(parse_attr(&attributes, "rows", 1)),
+                            clips: Vec::new(),
+                        });
+                    },
+                    "clip" => {
+                        let sheet = try!(sheet.as_mut().ok_or("clip outside of a sheet".to_string()));
+                        let clip = Clip {
+                            name: try!(attr(&attributes, "name").ok_or(format!("clip without a name in sheet {}", sheet.name))).to_string(),
+                            first: try!(parse_attr(&attributes, "first", 0)),
+                            last: try!(parse_attr(&attributes, "last", 0)),
+                            fps: try!(parse_attr(&attributes, "fps", 10.0)),
+                            looping: try!(parse_attr::<u32>(&attributes, "loop", 1)) != 0,
+                        };
+                        if clip.first > clip.last || clip.last >= sheet.frame_count() {
+                            return Err(format!("clip {} of sheet {} plays frames {} to {}, the sheet has {}",
+                                clip.name, sheet.name, clip.first, clip.last, sheet.frame_count()));
+                        }
+                        sheet.clips.push(clip);
+                    },
+                    _ => (),
+                }
+            },
+            XmlEvent::EndElement { name } => {
+                if name.local_name == "sheet" {
+                    if let Some(sheet) = sheet.take() {
+                        sheets.push(sheet);
+                    }
+                }
+            },
+            _ => (),
+        }
+    }
+
+    Ok(sheets)
+}
+
+// Plays clips of a sprite sheet on an entity. frames holds the atlas uv of every sheet frame,
+// the current one gets copied into the entity's Sprite.
+pub struct SpriteAnimation {
+    clips: Vec<Clip>,
+    frames: Vec<[f32; 4]>,
+    current: usize,
+    elapsed: f32,
+    frame: usize,
+}
+
+impl SpriteAnimation {
+    // starts out playing the first clip
+    pub fn new(clips: Vec<Clip>, frames: Vec<[f32; 4]>) -> SpriteAnimation {
+        let frame = clips.get(0).map(|clip| clip.first).unwrap_or(0);
+        SpriteAnimation {
+            clips: clips,
+            frames: frames,
+            current: 0,
+            elapsed: 0.0,
+            frame: frame,
+        }
+    }
+
+    pub fn clip_name(&self) -> Option<&str> {
+        self.clips.get(self.current).map(|clip| clip.name.as_str())
+    }
+
+    // switches to the named clip from its start, unless it's already playing.
+    // Returns false if there's no clip with that name.
+    pub fn play(&mut self, name: &str) -> bool {
+        if self.clip_name() == Some(name) {
+            return true
+        }
+        match self.clips.iter().position(|clip| clip.name == name) {
+            Some(idx) => {
+                self.current = idx;
+                self.elapsed = 0.0;
+                true
+            },
+            None => false,
+        }
+    }
+
+    // returns true when the visible frame changed
+    pub fn advance(&mut self, delta: f32) -> bool {
+        let frame = match self.clips.get(self.current) {
+            Some(clip) => {
+                self.elapsed += delta;
+                clip_frame(clip, self.elapsed)
+            },
+            None => return false,
+        };
+        let changed = frame != self.frame;
+        self.frame = frame;
+        changed
+    }
+
+    pub fn uv(&self) -> Option<[f32; 4]> {
+        self.frames.get(self.frame).cloned()
+    }
+}
+
+impl Component for SpriteAnimation {
+    type Storage = VecStorage<SpriteAnimation>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    fn clip(looping: bool) -> Clip {
+        Clip { name: "run".to_string(), first: 4, last: 7, fps: 10.0, looping: looping }
+    }
+
+    #[test]
+    fn looping_clip_wraps() {
+        assert_eq!(clip_frame(&clip(true), 0.0), 4);
+        assert_eq!(clip_frame(&clip(true), 0.25), 6);
+        assert_eq!(clip_frame(&clip(true), 0.45), 4);
+    }
+
+    #[test]
+    fn clip_without_loop_holds_last_frame() {
+        assert_eq!(clip_frame(&clip(false), 0.35), 7);
+        assert_eq!(clip_frame(&clip(false), 10.0), 7);
+    }
+
+    #[test]
+    fn frame_uv_goes_down_the_sheet() {
+        let sheet = SpriteSheet { name: "a".to_string(), image: "a.png".to_string(), columns: 4, rows: 2, clips: Vec::new() };
+        assert_eq!(sheet.frame_uv(0), [0.0, 0.0, 0.25, 0.5]);
+        assert_eq!(sheet.frame_uv(5), [0.25, 0.5, 0.5, 1.0]);
+    }
+
+    #[test]
+    fn reads_resources_animations() {
+        let sheets = read_sprite_sheets(File::open("./resources/animations.xml").unwrap()).unwrap();
+        let player = sheets.iter().find(|sheet| sheet.name == "player").unwrap();
+        let names: Vec<&str> = player.clips.iter().map(|clip| clip.name.as_str()).collect();
+        assert_eq!(names, vec!["idle", "run", "jump"]);
+        assert!(!player.clips[2].looping);
+    }
+
+    #[test]
+    fn rejects_clip_past_the_sheet() {
+        let data = r#"<animations><sheet name="a" image="a.png" columns="2" rows="1"><clip name="b" first="0" last="2"/></sheet></animations>"#;
+        assert!(read_sprite_sheets(data.as_bytes()).is_err());
+    }
+}
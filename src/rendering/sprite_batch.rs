@@ -0,0 +1,262 @@
+extern crate gfx;
+extern crate amethyst;
+
+use std::fmt;
+use std::fmt::{Debug, Formatter};
+
+use amethyst::ecs::{Component, VecStorage};
+use amethyst::renderer::pass::{DrawFlat, Pass};
+use amethyst::renderer::pass::PassDescription;
+use amethyst::renderer::{Pipeline, Scene};
+use amethyst::renderer::target::GeometryBuffer;
+use amethyst::gfx_device::gfx_types;
+
+use gfx::traits::FactoryExt;
+
+use error::{Error, Result};
+use rect::gen_rectangle_uv;
+use rendering::{AtlasRegion, CBTarget, Shared, TextureAtlas};
+
+// most sprites a single frame can draw, anything past this is dropped
+pub const MAX_SPRITES: usize = 4096;
+const VERTS_PER_SPRITE: usize = 6;
+
+gfx_defines!{
+    vertex SpriteVertex {
+        pos: [f32; 3] = "a_Pos",
+        uv: [f32; 2] = "a_Uv",
+    }
+
+    constant SpriteLocals {
+        view: [[f32; 4]; 4] = "u_View",
+        proj: [[f32; 4]; 4] = "u_Proj",
+    }
+
+    pipeline sprite_pipe {
+        vbuf: gfx::VertexBuffer<SpriteVertex> = (),
+        locals: gfx::ConstantBuffer<SpriteLocals> = "b_SpriteLocals",
+        page: gfx::TextureSampler<[f32; 4]> = "t_Page",
+        out_color: gfx::BlendTarget<gfx::format::Rgba8> =
+            ("Target0", gfx::state::MASK_ALL, gfx::preset::blend::ALPHA),
+    }
+}
+
+// A textured quad drawn by the sprite batch, centred on the entity's LocalTransform
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sprite {
+    // atlas page the texture is on
+    pub page: usize,
+    // [left, top, right, bottom] in the page's texture space
+    pub uv: [f32; 4],
+    // width and height in map pixels
+    pub size: [f32; 2],
+    // sprites with a higher depth are drawn on top
+    pub depth: f32,
+}
+
+impl Sprite {
+    pub fn new(region: &AtlasRegion, size: [f32; 2], depth: f32) -> Sprite {
+        Sprite {
+            page: region.page,
+            uv: region.uv,
+            size: size,
+            depth: depth,
+        }
+    }
+}
+
+impl Component for Sprite {
+    type Storage = VecStorage<Sprite>;
+}
+
+// The quads of every sprite for the frame, sorted so each atlas page is drawn in as few calls as
+// possible. Filled on the cpu every update, uploaded and drawn by SpriteBatchPass.
+pub struct SpriteBatch {
+    vertices: Vec<SpriteVertex>,
+    // atlas page and the range of sprites drawn with it, in draw order
+    runs: Vec<(usize, usize, usize)>,
+    dirty: bool,
+}
+
+impl SpriteBatch {
+    pub fn new() -> SpriteBatch {
+        SpriteBatch {
+            vertices: Vec::with_capacity(MAX_SPRITES * VERTS_PER_SPRITE),
+            runs: Vec::new(),
+            dirty: false,
+        }
+    }
+
+    // Rebuilds the batch from sprites and the position of their entities. Sprites are drawn
+    // back to front by depth, and within the same depth grouped by page. Sprites with a nan or
+    // infinite depth have no place in that order and are dropped.
+    pub fn build(&mut self, mut sprites: Vec<(Sprite, [f32; 2])>) {
+        sprites.retain(|&(sprite, _)| sprite.depth.is_finite());
+        sprites.sort_by(|a, b| {
+            a.0.depth.partial_cmp(&b.0.depth).unwrap().then(a.0.page.cmp(&b.0.page))
+        });
+        sprites.truncate(MAX_SPRITES);
+
+        self.vertices.clear();
+        self.runs.clear();
+        for (idx, &(sprite, position)) in sprites.iter().enumerate() {
+            for vertex in gen_rectangle_uv(sprite.size[0], sprite.size[1], sprite.uv) {
+                self.vertices.push(SpriteVertex {
+                    pos: [vertex.pos[0] + position[0], vertex.pos[1] + position[1], 0.0],
+                    uv: vertex.tex_coord,
+                });
+            }
+
+            let extends_run = match self.runs.last() {
+                Some(&(page, _, _)) => page == sprite.page,
+                None => false,
+            };
+            if extends_run {
+                self.runs.last_mut().unwrap().2 = idx + 1;
+            } else {
+                self.runs.push((sprite.page, idx, idx + 1));
+            }
+        }
+        self.dirty = true;
+    }
+}
+
+// Draws the whole sprite batch with one call per run of sprites sharing an atlas page
+pub struct SpriteBatchPass {
+    batch: Shared<SpriteBatch>,
+    // one set of pipeline data per atlas page, they all share the vertex buffer
+    pages: Vec<sprite_pipe::Data<gfx_types::Resources>>,
+    pso: gfx::PipelineState<gfx_types::Resources, sprite_pipe::Meta>,
+}
+
+impl Debug for SpriteBatchPass {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "SpriteBatchPass")
+    }
+}
+
+impl PassDescription for SpriteBatchPass {}
+
+impl SpriteBatchPass {
+    pub fn new<F>(factory: &mut F, batch: Shared<SpriteBatch>, atlas: &TextureAtlas, target: &CBTarget) -> Result<SpriteBatchPass>
+        where F: gfx::Factory<gfx_types::Resources>
+    {
+        let vbuf = try!(factory.create_buffer(
+            MAX_SPRITES * VERTS_PER_SPRITE,
+            gfx::buffer::Role::Vertex,
+            gfx::memory::Usage::Dynamic,
+            gfx::memory::Bind::empty()
+        ).map_err(|e| Error::gpu("sprite vertex buffer", e)));
+        let locals = factory.create_constant_buffer(1);
+        let sampler = factory.create_sampler(
+            gfx::texture::SamplerInfo::new(
+                gfx::texture::FilterMethod::Scale,
+                gfx::texture::WrapMode::Clamp
+            )
+        );
+
+        let pages = atlas.pages.iter().map(|page| {
+            sprite_pipe::Data {
+                vbuf: vbuf.clone(),
+                locals: locals.clone(),
+                page: (page.view.clone(), sampler.clone()),
+                out_color: target.color.clone(),
+            }
+        }).collect();
+
+        let pso = try!(factory.create_pipeline_simple(
+            include_bytes!("shader/sprite_150.glslv"),
+            include_bytes!("shader/sprite_150.glslf"),
+            sprite_pipe::new()
+        ).map_err(|e| Error::gpu("sprite pipeline", e)));
+
+        Ok(SpriteBatchPass {
+            batch: batch,
+            pages: pages,
+            pso: pso,
+        })
+    }
+}
+
+impl Pass<gfx_types::Resources> for SpriteBatchPass {
+    type Arg = DrawFlat;
+    type Target = GeometryBuffer<gfx_types::Resources>;
+
+    fn apply<C>(&self,
+        _: &DrawFlat,
+        _: &GeometryBuffer<gfx_types::Resources>,
+        _: &Pipeline,
+        scene: &Scene<gfx_types::Resources>,
+        encoder: &mut gfx::Encoder<gfx_types::Resources, C>)
+    where C: gfx::CommandBuffer<gfx_types::Resources>
+    {
+        let mut batch = self.batch.lock().unwrap();
+        let first_page = match self.pages.get(0) {
+            Some(page) => page,
+            None => return,
+        };
+
+        if batch.dirty {
+            // never more than the buffer holds, so the upload can't fail
+            let count = batch.vertices.len().min(MAX_SPRITES * VERTS_PER_SPRITE);
+            encoder.update_buffer(&first_page.vbuf, &batch.vertices[..count], 0).unwrap();
+            batch.dirty = false;
+        }
+        encoder.update_constant_buffer(&first_page.locals, &SpriteLocals {
+            view: scene.camera.view,
+            proj: scene.camera.proj,
+        });
+
+        for &(page, start, end) in batch.runs.iter() {
+            let slice = gfx::Slice {
+                start: (start * VERTS_PER_SPRITE) as u32,
+                end: (end * VERTS_PER_SPRITE) as u32,
+                base_vertex: 0,
+                instances: None,
+                buffer: gfx::IndexBuffer::Auto,
+            };
+            encoder.draw(&slice, &self.pso, &self.pages[page]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sprite(page: usize, depth: f32) -> Sprite {
+        Sprite { page: page, uv: [0.0, 0.0, 1.0, 1.0], size: [2.0, 2.0], depth: depth }
+    }
+
+    #[test]
+    fn sprites_sharing_a_page_are_one_run() {
+        let mut batch = SpriteBatch::new();
+        batch.build(vec![(sprite(0, 0.0), [0.0, 0.0]), (sprite(0, 0.0), [4.0, 0.0]), (sprite(0, 1.0), [8.0, 0.0])]);
+        assert_eq!(batch.vertices.len() / VERTS_PER_SPRITE, 3);
+        assert_eq!(batch.runs, vec![(0, 0, 3)]);
+    }
+
+    #[test]
+    fn sorts_by_depth_then_page() {
+        let mut batch = SpriteBatch::new();
+        batch.build(vec![(sprite(1, 0.0), [0.0, 0.0]), (sprite(0, 1.0), [0.0, 0.0]), (sprite(0, 0.0), [0.0, 0.0])]);
+        assert_eq!(batch.runs, vec![(0, 0, 1), (1, 1, 2), (0, 2, 3)]);
+    }
+
+    #[test]
+    fn drops_sprites_without_a_finite_depth() {
+        let mut batch = SpriteBatch::new();
+        batch.build(vec![(sprite(0, ::std::f32::NAN), [0.0, 0.0]), (sprite(1, 0.0), [0.0, 0.0]),
+            (sprite(0, ::std::f32::INFINITY), [0.0, 0.0])]);
+        assert_eq!(batch.vertices.len() / VERTS_PER_SPRITE, 1);
+        assert_eq!(batch.runs, vec![(1, 0, 1)]);
+    }
+
+    #[test]
+    fn quads_are_placed_at_the_entity() {
+        let mut batch = SpriteBatch::new();
+        batch.build(vec![(sprite(0, 0.0), [10.0, 20.0])]);
+        assert_eq!(batch.vertices[0].pos, [9.0, 19.0, 0.0]);
+        assert_eq!(batch.vertices[2].pos, [11.0, 21.0, 0.0]);
+    }
+}
@@ -0,0 +1,142 @@
+extern crate tiled;
+
+use rendering::gid_to_coords;
+
+pub struct AnimationFrame {
+    // pixel x, y and tileset slot + 1, laid out like the first three components of TileMapData
+    pub data: [f32; 3],
+    // seconds
+    pub duration: f32,
+}
+
+// A tileset tile with <animation> frames, along with every map cell that shows it.
+// All cells share the same clock, the same way Tiled previews them.
+pub struct AnimatedTile {
+    pub gid: u32,
+    frames: Vec<AnimationFrame>,
+    current: usize,
+    elapsed: f32,
+    // layer, xpos, ypos and flip bits of each cell using this tile
+    pub cells: Vec<(usize, usize, usize, f32)>,
+}
+
+impl AnimatedTile {
+    pub fn new(gid: u32, frames: Vec<AnimationFrame>) -> AnimatedTile {
+        AnimatedTile {
+            gid: gid,
+            frames: frames,
+            current: 0,
+            elapsed: 0.0,
+            cells: Vec::new(),
+        }
+    }
+
+    // returns true when the visible frame changed
+    pub fn advance(&mut self, delta: f32) -> bool {
+        if self.frames.len() < 2 {
+            return false
+        }
+        let total: f32 = self.frames.iter().map(|frame| frame.duration.max(0.0)).sum();
+        if total <= 0.0 {
+            // every frame is zero length, there's nothing to show for any time
+            return false
+        }
+        let start = self.current;
+        // whole loops end up back on the same frame, so a long delta goes round at most once
+        self.elapsed = (self.elapsed + delta) % total;
+        while self.elapsed >= self.frames[self.current].duration {
+            self.elapsed -= self.frames[self.current].duration.max(0.0);
+            self.current = (self.current + 1) % self.frames.len();
+        }
+        self.current != start
+    }
+
+    pub fn frame_data(&self, flip: f32) -> [f32; 4] {
+        let data = self.frames[self.current].data;
+        [data[0], data[1], data[2], flip]
+    }
+}
+
+pub fn collect_animated_tiles(map: &tiled::Map) -> Vec<AnimatedTile> {
+    let mut animated_tiles = Vec::new();
+    for tileset in map.tilesets.iter() {
+        for tile in tileset.tiles.iter() {
+            let animation = match tile.animation {
+                Some(ref animation) => animation,
+                None => continue,
+            };
+            let frames = animation.iter().filter_map(|frame| {
+                gid_to_coords(&map.tilesets, tileset.first_gid + frame.tile_id).map(|coords| {
+                    AnimationFrame {
+                        data: [coords.pixel[0], coords.pixel[1], (coords.tileset + 1) as f32],
+                        duration: frame.duration as f32 / 1000.0,
+                    }
+                })
+            }).collect();
+            animated_tiles.push(AnimatedTile::new(tileset.first_gid + tile.id, frames));
+        }
+    }
+    animated_tiles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tile(durations: &[f32]) -> AnimatedTile {
+        let frames = durations.iter().enumerate().map(|(idx, &duration)| {
+            AnimationFrame { data: [idx as f32 * 32.0, 0.0, 1.0], duration: duration }
+        }).collect();
+        AnimatedTile::new(1, frames)
+    }
+
+    #[test]
+    fn stays_on_a_frame_until_its_time_is_up() {
+        let mut animated = tile(&[0.5, 0.25, 0.25]);
+        assert!(!animated.advance(0.25));
+        assert_eq!(animated.current, 0);
+        assert!(animated.advance(0.25));
+        assert_eq!(animated.current, 1);
+        assert_eq!(animated.frame_data(2.0), [32.0, 0.0, 1.0, 2.0]);
+    }
+
+    #[test]
+    fn deltas_longer_than_a_frame_skip_ahead() {
+        let mut animated = tile(&[0.5, 0.25, 0.25]);
+        assert!(animated.advance(0.8));
+        assert_eq!(animated.current, 2);
+        assert!((animated.elapsed - 0.05).abs() < 1e-5);
+    }
+
+    #[test]
+    fn wraps_past_the_last_frame() {
+        let mut animated = tile(&[0.5, 0.25, 0.25]);
+        animated.advance(0.8);
+        assert!(animated.advance(0.25));
+        assert_eq!(animated.current, 0);
+
+        // several whole loops and a bit more
+        assert!(animated.advance(3.5));
+        assert_eq!(animated.current, 1);
+        // exactly one loop lands on the same frame
+        assert!(!animated.advance(1.0));
+        assert_eq!(animated.current, 1);
+    }
+
+    #[test]
+    fn zero_length_frames_are_skipped() {
+        let mut animated = tile(&[0.5, 0.0, 0.5]);
+        assert!(animated.advance(0.5));
+        assert_eq!(animated.current, 2);
+
+        let mut animated = tile(&[0.0, 0.0]);
+        assert!(!animated.advance(1.0));
+        assert_eq!(animated.current, 0);
+    }
+
+    #[test]
+    fn single_frames_never_change() {
+        let mut animated = tile(&[0.1]);
+        assert!(!animated.advance(10.0));
+    }
+}
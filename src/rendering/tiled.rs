@@ -4,8 +4,10 @@ extern crate tiled;
 extern crate genmesh;
 extern crate cgmath;
 
+use std::cell::{Cell, RefCell};
 use std::fmt;
 use std::fmt::{Debug, Formatter};
+use std::path::Path;
 
 use amethyst::renderer::pass::{DrawFlat, Pass};
 use amethyst::renderer::{Pipeline, Scene};
@@ -13,21 +15,27 @@ use amethyst::renderer::target::{ColorBuffer, GeometryBuffer};
 use amethyst::renderer::pass::PassDescription;
 use amethyst::gfx_device::gfx_types;
 
+use error::{Error, Result};
 use rendering;
+use rendering::{gid_to_coords, split_gid, collect_animated_tiles, AnimatedTile, DirtyRanges, Shared, TextureAtlas};
 
 use gfx::traits::FactoryExt;
 use genmesh::{Vertices, Triangulate};
 use genmesh::generators::{Plane, SharedVertex, IndexedPolygon};
-use loader;
 
-use cgmath::{SquareMatrix, Matrix4, AffineMatrix3};
-use cgmath::{Point3, Vector3};
-use cgmath::{Transform};
+use cgmath::{SquareMatrix, Matrix4};
+use cgmath::Vector3;
 
-// this is a value based on a max buffer size (and hence tilemap size) of 64x64
-// I imagine you would have a max buffer length, with multiple TileMap instances
-// of varying sizes based on current screen resolution
-pub const TILEMAP_BUF_LENGTH: usize = 4096;
+// Entries in each layer's gpu window, 2304 vec4s keeps the uniform block at 36KB which
+// drivers accept. Views needing more tiles than this get zoomed in, see fit_view.
+// Keep in sync with TILEMAP_BUF_LENGTH in shader/tilemap_150.glslf
+pub const TILEMAP_BUF_LENGTH: usize = 2304;
+
+// number of tileset textures the shader can pick from. Each tileset gets its own
+// sampler slot, unused slots are bound to the first tileset so the pipeline is always complete
+pub const MAX_TILESETS: usize = 4;
+
+pub type TilesheetView = gfx::handle::ShaderResourceView<gfx_types::Resources, [f32; 4]>;
 
 // Actual tilemap data that makes up the elements of the UBO.
 // NOTE: It may be a bug, but it appears that
@@ -46,8 +54,9 @@ gfx_defines!{
 
     constant TilemapStuff {
         world_size: [f32; 4] = "u_WorldSize",
-        tilesheet_size: [f32; 4] = "u_TilesheetSize",
-        offsets: [f32; 2] = "u_TileOffsets",
+        tilesheet_sizes: [[f32; 4]; 4] = "u_TilesheetSizes",
+        tilesheet_offsets: [[f32; 4]; 4] = "u_TilesheetOffsets",
+        opacity: f32 = "u_Opacity",
     }
 
     vertex VertexData {
@@ -61,15 +70,19 @@ gfx_defines!{
         // tilemap stuff
         tilemap: gfx::ConstantBuffer<TileMapData> = "b_TileMap",
         tilemap_cb: gfx::ConstantBuffer<TilemapStuff> = "b_PsLocals",
-        tilesheet: gfx::TextureSampler<[f32; 4]> = "t_TileSheet",
+        tilesheet0: gfx::TextureSampler<[f32; 4]> = "t_TileSheet0",
+        tilesheet1: gfx::TextureSampler<[f32; 4]> = "t_TileSheet1",
+        tilesheet2: gfx::TextureSampler<[f32; 4]> = "t_TileSheet2",
+        tilesheet3: gfx::TextureSampler<[f32; 4]> = "t_TileSheet3",
         // output
-        out_color: gfx::RenderTarget<gfx::format::Rgba8> = "Target0",
+        out_color: gfx::BlendTarget<gfx::format::Rgba8> =
+            ("Target0", gfx::state::MASK_ALL, gfx::preset::blend::ALPHA),
         out_depth: gfx::DepthTarget<gfx::format::DepthStencil> =
             gfx::preset::depth::LESS_EQUAL_WRITE,
     }
 }
 
-type CBTarget = ColorBuffer<gfx_types::Resources>;
+pub type CBTarget = ColorBuffer<gfx_types::Resources>;
 
 impl TileMapData {
     pub fn new_empty() -> TileMapData {
@@ -78,28 +91,90 @@ impl TileMapData {
     pub fn new(data: [f32; 4]) -> TileMapData {
         TileMapData { data: data }
     }
+
+    // z is the tileset slot + 1, 0 is left for empty cells so the shader can skip them.
+    // w carries the flip bits
+    pub fn from_gid(tilesets: &[tiled::Tileset], raw_gid: u32) -> TileMapData {
+        let (gid, flip) = split_gid(raw_gid);
+        match gid_to_coords(tilesets, gid) {
+            Some(coords) => TileMapData::new([coords.pixel[0], coords.pixel[1], (coords.tileset + 1) as f32, flip.bits()]),
+            None => TileMapData::new_empty(),
+        }
+    }
+}
+
+// A tileset's image as packed in the texture atlas
+#[derive(Clone)]
+pub struct Tilesheet {
+    pub view: TilesheetView,
+    // top left of the tileset image in the atlas page, in pixels
+    pub offset: [f32; 2],
+    pub page_size: [f32; 2],
+}
+
+// Checks the map only uses what the tilemap renderer can draw, path is where it was loaded from
+pub fn check_supported(map: &tiled::Map, path: &Path) -> Result<()> {
+    if map.orientation != tiled::Orientation::Orthogonal {
+        return Err(Error::unsupported(path, format!("{:?} orientation, only orthogonal maps are drawn", map.orientation)));
+    }
+    if map.tilesets.is_empty() {
+        return Err(Error::unsupported(path, "a map without tilesets".to_string()));
+    }
+    if map.tilesets.len() > MAX_TILESETS {
+        return Err(Error::unsupported(path, format!("{} tilesets, at most {} can be used", map.tilesets.len(), MAX_TILESETS)));
+    }
+    if let Some(tileset) = map.tilesets.iter().find(|tileset| tileset.images.is_empty()) {
+        return Err(Error::unsupported(path, format!("tileset {} is an image collection, only single image tilesets are drawn", tileset.name)));
+    }
+    Ok(())
+}
+
+// where each tileset's image lives in the atlas, in the same order as map.tilesets.
+// Tilesets packed onto the same page share a texture, so switching between them costs no binds
+pub fn load_tilesheets(atlas: &TextureAtlas, map: &tiled::Map) -> Result<Vec<Tilesheet>> {
+    map.tilesets.iter().map(|tileset| {
+        let source = match tileset.images.get(0) {
+            Some(image) => &image.source,
+            None => return Err(Error::Asset(format!("tileset {} has no image to upload", tileset.name))),
+        };
+        let region = try!(atlas.region(source)
+            .ok_or_else(|| Error::Asset(format!("tileset image {} wasn't packed into the texture atlas", source))));
+        let page = &atlas.pages[region.page];
+        Ok(Tilesheet {
+            view: page.view.clone(),
+            offset: [region.rect[0] as f32, region.rect[1] as f32],
+            page_size: [page.size[0] as f32, page.size[1] as f32],
+        })
+    }).collect()
 }
 
 pub struct TileMapPlane {
     pub params: pipe::Data<gfx_types::Resources>,
     pub slice: gfx::Slice<gfx_types::Resources>,
-    proj_stuff: ProjectionStuff,
-    proj_dirty: bool,
+    // where the plane sits in the world, view and projection come from the sprite camera
+    model: [[f32; 4]; 4],
     tm_stuff: TilemapStuff,
-    tm_dirty: bool,
+    // tm_stuff hasn't been uploaded yet
+    tm_dirty: Cell<bool>,
     pub data: Vec<TileMapData>,
+    // entries changed since the last upload, taken when the buffer is drawn
+    dirty: RefCell<DirtyRanges>,
 }
 
 impl TileMapPlane {
-    pub fn new<F>(factory: &mut F, tilemap: &tiled::Map, aspect_ratio: f32 , target: &CBTarget) -> TileMapPlane
+    // the plane covers charmap_size tiles, the window of the map that is uploaded to the gpu
+    pub fn new<F>(factory: &mut F, tilemap: &tiled::Map, charmap_size: [usize; 2], tilesheets: &[Tilesheet], opacity: f32, target: &CBTarget) -> Result<TileMapPlane>
     where F: gfx::Factory<gfx_types::Resources>
     {
-        let half_width = (tilemap.width * tilemap.tile_width) / 2;
-        let half_height = (tilemap.height * tilemap.tile_height) / 2;
+        if tilesheets.is_empty() {
+            return Err(Error::Asset("a tilemap plane needs at least one tilesheet to bind".to_string()));
+        }
+        let half_width = (charmap_size[0] as u32 * tilemap.tile_width) / 2;
+        let half_height = (charmap_size[1] as u32 * tilemap.tile_height) / 2;
 
-        let total_size = tilemap.width * tilemap.height;
+        let total_size = charmap_size[0] * charmap_size[1];
 
-        let plane = Plane::subdivide(tilemap.width as usize, tilemap.height as usize);
+        let plane = Plane::subdivide(charmap_size[0], charmap_size[1]);
 
         let vertex_data: Vec<VertexData> = plane.shared_vertex_iter().map(|(raw_x, raw_y)| {
             let vertex_x = half_width as f32 * raw_x;
@@ -107,8 +182,8 @@ impl TileMapPlane {
 
             let u_pos = (1.0 + raw_x) / 2.0;
             let v_pos = (1.0 + raw_y) / 2.0;
-            let tilemap_x = (u_pos * tilemap.width as f32).floor();
-            let tilemap_y = (v_pos * tilemap.height as f32).floor();
+            let tilemap_x = (u_pos * charmap_size[0] as f32).floor();
+            let tilemap_y = (v_pos * charmap_size[1] as f32).floor();
 
             VertexData {
                 pos: [vertex_x, vertex_y, 0.0],
@@ -124,133 +199,250 @@ impl TileMapPlane {
 
         let (vbuf, slice) = factory.create_vertex_buffer_with_slice(&vertex_data, &index_data[..]);
 
-        let tileset = tilemap.tilesets.get(0).unwrap(); // working under the assumption i will only use one tileset
-        let image = tileset.images.get(0).unwrap();
-        let tiles_texture = loader::gfx_load_texture(factory, &image.source);
+        // nearest filtering, linear blurs pixel art and bleeds neighbouring tiles into the seams
+        let sampler = factory.create_sampler(
+            gfx::texture::SamplerInfo::new(
+                gfx::texture::FilterMethod::Scale,
+                gfx::texture::WrapMode::Clamp
+            )
+        );
+        let tilesheet_slot = |idx: usize| {
+            let sheet = tilesheets.get(idx).unwrap_or(&tilesheets[0]);
+            (sheet.view.clone(), sampler.clone())
+        };
+
+        let mut tilesheet_sizes = [[0.0; 4]; MAX_TILESETS];
+        let mut tilesheet_offsets = [[0.0; 4]; MAX_TILESETS];
+        for (idx, (tileset, sheet)) in tilemap.tilesets.iter().zip(tilesheets.iter()).take(MAX_TILESETS).enumerate() {
+            tilesheet_sizes[idx] = [tileset.tile_width as f32, tileset.tile_height as f32, sheet.page_size[0], sheet.page_size[1]];
+            tilesheet_offsets[idx] = [sheet.offset[0], sheet.offset[1], 0.0, 0.0];
+        }
 
         let params = pipe::Data {
             vbuf: vbuf,
             projection_cb: factory.create_constant_buffer(1),
             tilemap: factory.create_constant_buffer(TILEMAP_BUF_LENGTH),
             tilemap_cb: factory.create_constant_buffer(1),
-            tilesheet: (tiles_texture, factory.create_sampler_linear()),
+            tilesheet0: tilesheet_slot(0),
+            tilesheet1: tilesheet_slot(1),
+            tilesheet2: tilesheet_slot(2),
+            tilesheet3: tilesheet_slot(3),
             out_color: target.color.clone(),
             out_depth: target.output_depth.clone(),
         };
 
-        // TODO: change the coords here
-        let view: AffineMatrix3<f32> = Transform::look_at(
-            Point3::new(0.0, 0.0, 800.0),
-            Point3::new(0.0, 0.0, 0.0),
-            Vector3::unit_y(),
-        );
-
-        let mut map_data = Vec::with_capacity(total_size as usize);
+        let mut map_data = Vec::with_capacity(total_size);
         for _ in 0..total_size {
             map_data.push(TileMapData::new_empty());
         }
 
-        TileMapPlane{
+        Ok(TileMapPlane{
             slice: slice,
             params: params,
-            proj_stuff: ProjectionStuff {
-                model: Matrix4::identity().into(),
-                view: view.mat.into(),
-                proj: cgmath::perspective(cgmath::deg(60.0f32), aspect_ratio, 0.1, 4000.0).into(),
-            },
-            proj_dirty: true,
+            model: Matrix4::identity().into(),
             tm_stuff: TilemapStuff{
-                world_size: [tilemap.width as f32, tilemap.height as f32, tilemap.tile_width as f32, 0.0],
-                tilesheet_size: [tileset.tile_width as f32, tileset.tile_height as f32, tileset.images[0].width as f32, tileset.images[0].height as f32],
-                offsets: [0.0, 0.0],
+                world_size: [charmap_size[0] as f32, charmap_size[1] as f32, tilemap.tile_width as f32, 0.0],
+                tilesheet_sizes: tilesheet_sizes,
+                tilesheet_offsets: tilesheet_offsets,
+                opacity: opacity,
             },
-            tm_dirty: true,
+            tm_dirty: Cell::new(true),
             data: map_data,
-        }
+            dirty: RefCell::new(DirtyRanges::new()),
+        })
     }
 
-    fn prepare_buffers<C>(&self, encoder: &mut gfx::Encoder<gfx_types::Resources, C>, update_data: bool) where C: gfx::CommandBuffer<gfx_types::Resources> {
-        if update_data {
-            encoder.update_buffer(&self.params.tilemap, &self.data, 0).unwrap();
-        }
-        if self.proj_dirty {
-            encoder.update_constant_buffer(&self.params.projection_cb, &self.proj_stuff);
+    // proj and view are the sprite camera's, so tiles share its pixel space
+    pub fn prepare_buffers<C>(&self, encoder: &mut gfx::Encoder<gfx_types::Resources, C>, proj: [[f32; 4]; 4], view: [[f32; 4]; 4]) where C: gfx::CommandBuffer<gfx_types::Resources> {
+        for (start, end) in self.dirty.borrow_mut().take() {
+            encoder.update_buffer(&self.params.tilemap, &self.data[start..end], start).unwrap();
         }
-        if self.tm_dirty {
+        encoder.update_constant_buffer(&self.params.projection_cb, &ProjectionStuff {
+            model: self.model,
+            view: view,
+            proj: proj,
+        });
+        if self.tm_dirty.get() {
             encoder.update_constant_buffer(&self.params.tilemap_cb, &self.tm_stuff);
+            self.tm_dirty.set(false);
         }
     }
 
-    pub fn update_x_offset(&mut self, amt: f32) {
-        self.tm_stuff.offsets[0] = amt;
-        self.tm_dirty = true;
+    // places the centre of the plane in the world, in pixels
+    pub fn set_translation(&mut self, translation: [f32; 3]) {
+        self.model = Matrix4::from_translation(Vector3::new(translation[0], translation[1], translation[2])).into();
+    }
+
+    // queues the whole buffer for upload on the next draw
+    pub fn mark_all_dirty(&self) {
+        self.dirty.borrow_mut().add(0, self.data.len());
     }
 
-    pub fn update_y_offset(&mut self, amt: f32) {
-        self.tm_stuff.offsets[1] = amt;
-        self.tm_dirty = true;
+    // changes a single entry, only that part of the buffer gets uploaded on the next draw
+    pub fn set_data(&mut self, idx: usize, data: TileMapData) {
+        self.data[idx] = data;
+        self.dirty.borrow_mut().add(idx, idx + 1);
     }
+
 }
 
 fn populate_tilemap(tilemap: &mut TileMap, map_data: &tiled::Map) {
     let layers = &map_data.layers;
-    for layer in layers {
+    for (layer_idx, layer) in layers.iter().enumerate() {
         for (row, cols) in layer.tiles.iter().enumerate() {
-            for col in cols {
-                if *col != 0 {
-                    for tileset in map_data.tilesets.iter() {
-                        let image = &tileset.images[0];
-                        if tileset.first_gid as usize + tileset.tiles.len() - 1 <= *col as usize {
-                            let x = (*col as f32 * tilemap.tile_size) % image.width as f32;
-                            let y = (row as f32 * tilemap.tile_size) % image.height as f32;
-                            tilemap.set_tile(*col as usize, row, [x, y, 0.0, 0.0]);
-                            break
-                        }
-                    }
+            // Tiled stores rows top down, the plane buffer counts them bottom up
+            let ypos = map_data.height as usize - 1 - row;
+            for (xpos, raw_gid) in cols.iter().enumerate() {
+                let (gid, flip) = split_gid(*raw_gid);
+                if gid == 0 {
+                    continue
                 }
+                // animated cells start on the animation's current frame, not the tile's own image
+                let data = match tilemap.animated_tiles.iter_mut().find(|a| a.gid == gid) {
+                    Some(animated) => {
+                        animated.cells.push((layer_idx, xpos, ypos, flip.bits()));
+                        animated.frame_data(flip.bits())
+                    },
+                    None => TileMapData::from_gid(&map_data.tilesets, *raw_gid).data,
+                };
+                tilemap.set_tile(layer_idx, xpos, ypos, data);
             }
         }
     }
 }
 
-pub struct TileMap {
+// A single Tiled layer, with its own copy of the tile data and its own GPU buffers,
+// so layers can be drawn on top of each other in document order
+pub struct TileMapLayer {
+    pub name: String,
     pub tiles: Vec<TileMapData>,
-    pso: gfx::PipelineState<gfx_types::Resources, pipe::Meta>,
+    pub visible: bool,
+    pub opacity: f32,
     tilemap_plane: TileMapPlane,
+}
+
+// Number of tiles needed to cover view_size pixels at any scroll offset, capped to the map size.
+// One extra tile on each axis covers the partially scrolled tiles at the edges.
+pub fn window_tiles(view_size: [f32; 2], tile_size: f32, tilemap_size: [usize; 2]) -> [usize; 2] {
+    let tiles_x = (view_size[0] / tile_size).ceil() as usize + 1;
+    let tiles_y = (view_size[1] / tile_size).ceil() as usize + 1;
+    [tiles_x.min(tilemap_size[0]), tiles_y.min(tilemap_size[1])]
+}
+
+// The smallest whole zoom at which the window of tiles covering a window_size view fits in the
+// tile buffer, and the view size at that zoom. Large windows, eg 4K fullscreen with 32 pixel
+// tiles, need more tiles than the buffer holds and get drawn at 2x instead.
+pub fn fit_view(window_size: [f32; 2], tile_size: f32, tilemap_size: [usize; 2]) -> (u32, [f32; 2]) {
+    let mut scale = 1;
+    loop {
+        let view_size = [window_size[0] / scale as f32, window_size[1] / scale as f32];
+        let tiles = window_tiles(view_size, tile_size, tilemap_size);
+        if tiles[0] * tiles[1] <= TILEMAP_BUF_LENGTH {
+            return (scale, view_size)
+        }
+        scale += 1;
+    }
+}
+
+// Where a view with its bottom left at position can go without showing past the map's edges,
+// and the first tile of the window that covers it there. limit_coords is the furthest the
+// window can start and still fit in the map.
+pub fn scroll_window(position: [f32; 2], view_size: [f32; 2], tile_size: f32, tilemap_size: [usize; 2], limit_coords: [usize; 2]) -> ([f32; 2], [usize; 2]) {
+    let mut clamped = [0.0, 0.0];
+    let mut focus = [0, 0];
+    for axis in 0..2 {
+        let map_pixels = tilemap_size[axis] as f32 * tile_size;
+        let max_position = (map_pixels - view_size[axis]).max(0.0);
+        clamped[axis] = position[axis].max(0.0).min(max_position);
+        focus[axis] = ((clamped[axis] / tile_size).floor() as usize).min(limit_coords[axis]);
+    }
+    (clamped, focus)
+}
+
+fn charmap_for_view(view_size: [f32; 2], tile_size: f32, tilemap_size: [usize; 2]) -> Result<[usize; 2]> {
+    let charmap_size = window_tiles(view_size, tile_size, tilemap_size);
+    if charmap_size[0] * charmap_size[1] > TILEMAP_BUF_LENGTH {
+        return Err(Error::Asset(format!("a view of {:?} needs {:?} tiles, more than the {} the tile buffer holds",
+            view_size, charmap_size, TILEMAP_BUF_LENGTH)));
+    }
+    Ok(charmap_size)
+}
+
+// The full map stays on the cpu in each layer's tiles, only the charmap_size window
+// starting at focus_coords lives on the gpu. The planes are drawn in map pixels with y up,
+// one tile being tile_size pixels, so the sprite camera scrolls them like any other entity.
+// The window moves a tile at a time to keep the view covered.
+pub struct TileMap {
+    pub layers: Vec<TileMapLayer>,
+    pso: gfx::PipelineState<gfx_types::Resources, pipe::Meta>,
+    // kept around to rebuild the planes when the view changes size
+    tilesheets: Vec<Tilesheet>,
+    // the map's tilesets, to look up tiles placed at runtime by their gid
+    map_tilesets: Vec<tiled::Tileset>,
     tile_size: f32,
     tilemap_size: [usize; 2],
     charmap_size: [usize; 2],
     limit_coords: [usize; 2],
     focus_coords: [usize; 2],
-    focus_dirty: bool,
+    view_size: [f32; 2],
+    // bottom left of the view, in map pixels
+    scroll_position: [f32; 2],
+    animated_tiles: Vec<AnimatedTile>,
 }
 
 impl TileMap {
-    pub fn new<F>(map: &tiled::Map, factory: &mut F, aspect_ratio: f32, target: &CBTarget) -> TileMap
+    pub fn new<F>(map: &tiled::Map, factory: &mut F, atlas: &TextureAtlas, view_size: [f32; 2], target: &CBTarget) -> Result<TileMap>
         where F: gfx::Factory<gfx_types::Resources>
     {
-        let mut tiles = Vec::with_capacity((map.width * map.height) as usize);
-        for _ in 0..(map.width * map.height) {
-            tiles.push(TileMapData::new_empty());
+        let tilemap_size = [map.width as usize, map.height as usize];
+        let tile_size = map.tile_width as f32;
+        let charmap_size = try!(charmap_for_view(view_size, tile_size, tilemap_size));
+
+        let tilesheets = try!(load_tilesheets(atlas, map));
+
+        let mut layers = Vec::with_capacity(map.layers.len());
+        for layer in map.layers.iter() {
+            let mut tiles = Vec::with_capacity((map.width * map.height) as usize);
+            for _ in 0..(map.width * map.height) {
+                tiles.push(TileMapData::new_empty());
+            }
+
+            layers.push(TileMapLayer {
+                name: layer.name.clone(),
+                tiles: tiles,
+                visible: layer.visible,
+                opacity: layer.opacity,
+                tilemap_plane: try!(TileMapPlane::new(
+                    factory, map, charmap_size, &tilesheets, layer.opacity, target
+                )),
+            });
         }
 
-        TileMap {
-            tiles: tiles,
-            pso: factory.create_pipeline_simple(
-                include_bytes!("shader/tilemap_150.glslv"),
-                include_bytes!("shader/tilemap_150.glslf"),
-                pipe::new()
-            ).unwrap(),
-            tilemap_plane: TileMapPlane::new(
-                factory, map, aspect_ratio, target
-            ),
-            tile_size: map.tile_width as f32,
-            tilemap_size: [map.width as usize, map.height as usize],
-            charmap_size: [map.width as usize, map.height as usize],
-            limit_coords: [0, 0],
+        let pso = try!(factory.create_pipeline_simple(
+            include_bytes!("shader/tilemap_150.glslv"),
+            include_bytes!("shader/tilemap_150.glslf"),
+            pipe::new()
+        ).map_err(|e| Error::gpu("tilemap pipeline", e)));
+
+        let mut tilemap = TileMap {
+            layers: layers,
+            pso: pso,
+            tilesheets: tilesheets,
+            map_tilesets: map.tilesets.clone(),
+            tile_size: tile_size,
+            tilemap_size: tilemap_size,
+            charmap_size: charmap_size,
+            limit_coords: [tilemap_size[0] - charmap_size[0], tilemap_size[1] - charmap_size[1]],
             focus_coords: [0, 0],
-            focus_dirty: false,
-        }
+            view_size: view_size,
+            scroll_position: [0.0, 0.0],
+            animated_tiles: collect_animated_tiles(map),
+        };
+
+        populate_tilemap(&mut tilemap, map);
+        tilemap.set_focus([0, 0]);
+
+        Ok(tilemap)
     }
 
     pub fn set_focus(&mut self, focus: [usize; 2]) {
@@ -262,89 +454,131 @@ impl TileMap {
                 for xpos in self.focus_coords[0] .. self.focus_coords[0]+self.charmap_size[0] {
                     let tile_idx = (ypos * self.tilemap_size[0]) + xpos;
                     let charmap_idx = (charmap_ypos * self.charmap_size[0]) + charmap_xpos;
-                    self.tilemap_plane.data[charmap_idx] = self.tiles[tile_idx];
+                    for layer in self.layers.iter_mut() {
+                        layer.tilemap_plane.data[charmap_idx] = layer.tiles[tile_idx];
+                    }
                     charmap_xpos += 1;
                 }
                 charmap_ypos += 1;
             }
-            self.focus_dirty = true;
+
+            // planes are built around their centre, move it to the middle of the window
+            let translation = [
+                (focus[0] as f32 + self.charmap_size[0] as f32 / 2.0) * self.tile_size,
+                (focus[1] as f32 + self.charmap_size[1] as f32 / 2.0) * self.tile_size,
+                0.0,
+            ];
+            for layer in self.layers.iter_mut() {
+                layer.tilemap_plane.set_translation(translation);
+                layer.tilemap_plane.mark_all_dirty();
+            }
         } else {
             panic!("tried to set focus to {:?} with tilemap_size of {:?}", focus, self.tilemap_size);
         }
     }
 
-    pub fn apply_x_offset(&mut self, offset_amt: f32) {
-        let mut new_offset = self.tilemap_plane.tm_stuff.offsets[0] + offset_amt;
-        let curr_focus = self.focus_coords;
-        let new_x = if new_offset < 0.0 {
-            // move down
-            if self.focus_coords[0] == 0 {
-                new_offset = 0.0;
-                0
-            } else {
-                new_offset = self.tile_size + new_offset as f32;
-                self.focus_coords[0] - 1
-            }
-        } else if self.focus_coords[0] == self.limit_coords[0] {
-            // at top, no more offset
-            new_offset = 0.0;
-            self.focus_coords[0]
-        } else if new_offset >= self.tile_size {
-            new_offset = new_offset - self.tile_size as f32;
-            self.focus_coords[0] + 1
-        } else {
-            // no move
-            self.focus_coords[0]
-        };
-        if new_x != self.focus_coords[0] {
-            self.set_focus([new_x, curr_focus[1]]);
+    // Scrolls the view so its bottom left corner is at position, in map pixels. The window only
+    // gets refilled when the view crosses into a different tile.
+    pub fn scroll_to(&mut self, position: [f32; 2]) {
+        let (clamped, focus) = scroll_window(position, self.view_size, self.tile_size, self.tilemap_size, self.limit_coords);
+        self.scroll_position = clamped;
+
+        if focus != self.focus_coords {
+            self.set_focus(focus);
+        }
+    }
+
+    // Rebuilds the gpu window for a new view size, eg after the window got resized, and
+    // refills it around focus, the centre of the view in map pixels
+    pub fn resize<F>(&mut self, map: &tiled::Map, factory: &mut F, view_size: [f32; 2], focus: [f32; 2], target: &CBTarget) -> Result<()>
+        where F: gfx::Factory<gfx_types::Resources>
+    {
+        let charmap_size = try!(charmap_for_view(view_size, self.tile_size, self.tilemap_size));
+        for layer in self.layers.iter_mut() {
+            layer.tilemap_plane = try!(TileMapPlane::new(
+                factory, map, charmap_size, &self.tilesheets, layer.opacity, target
+            ));
         }
-        self.tilemap_plane.update_x_offset(new_offset);
+
+        self.charmap_size = charmap_size;
+        self.limit_coords = [self.tilemap_size[0] - charmap_size[0], self.tilemap_size[1] - charmap_size[1]];
+        self.view_size = view_size;
+
+        // the new planes are empty, so fill them before scrolling to where the view now starts
+        self.set_focus([0, 0]);
+        self.scroll_to([focus[0] - view_size[0] / 2.0, focus[1] - view_size[1] / 2.0]);
+        Ok(())
     }
+
+    pub fn apply_x_offset(&mut self, offset_amt: f32) {
+        let position = [self.scroll_position[0] + offset_amt, self.scroll_position[1]];
+        self.scroll_to(position);
+    }
+
     pub fn apply_y_offset(&mut self, offset_amt: f32) {
-        let mut new_offset = self.tilemap_plane.tm_stuff.offsets[1] + offset_amt;
-        let curr_focus = self.focus_coords;
-        let new_y = if new_offset < 0.0 {
-            // move down
-            if self.focus_coords[1] == 0 {
-                new_offset = 0.0;
-                0
-            } else {
-                new_offset = self.tile_size + new_offset as f32;
-                self.focus_coords[1] - 1
+        let position = [self.scroll_position[0], self.scroll_position[1] + offset_amt];
+        self.scroll_to(position);
+    }
+
+    // advances tile animations, writing changed frames into the cpu tiles and the
+    // slice of the gpu buffer currently in focus
+    pub fn update(&mut self, delta: f32) {
+        let tilemap_size = self.tilemap_size;
+        let charmap_size = self.charmap_size;
+        let focus = self.focus_coords;
+        for animated in self.animated_tiles.iter_mut() {
+            if !animated.advance(delta) {
+                continue
+            }
+            for &(layer_idx, xpos, ypos, flip) in animated.cells.iter() {
+                let data = TileMapData::new(animated.frame_data(flip));
+                write_tile(&mut self.layers[layer_idx], tilemap_size, focus, charmap_size, xpos, ypos, data);
             }
-        } else if self.focus_coords[1] == (self.tilemap_size[1] - self.charmap_size[1]) {
-            // at top, no more offset
-            new_offset = 0.0;
-            self.focus_coords[1]
-        } else if new_offset >= self.tile_size {
-            new_offset = new_offset - self.tile_size as f32;
-            self.focus_coords[1] + 1
-        } else {
-            // no move
-            self.focus_coords[1]
-        };
-        if new_y != self.focus_coords[1] {
-            self.set_focus([curr_focus[0], new_y]);
         }
-        self.tilemap_plane.update_y_offset(new_offset);
     }
 
-    fn calc_idx(&self, xpos: usize, ypos: usize) -> usize {
-        (ypos * self.tilemap_size[0]) + xpos
+    // Changes a tile on the cpu, and if it's inside the window on the gpu queues just that
+    // entry of the layer's buffer for upload on the next draw
+    pub fn set_tile(&mut self, layer: usize, xpos: usize, ypos: usize, data: [f32; 4]) {
+        let (tilemap_size, focus, charmap_size) = (self.tilemap_size, self.focus_coords, self.charmap_size);
+        write_tile(&mut self.layers[layer], tilemap_size, focus, charmap_size, xpos, ypos, TileMapData::new(data));
     }
-    pub fn set_tile(&mut self, xpos: usize, ypos: usize, data: [f32; 4]) {
-        let idx = self.calc_idx(xpos, ypos);
-        self.tiles[idx] = TileMapData::new(data);
+
+    // Places a tile by its gid, with Tiled's flip flags, or clears it with 0. Animated tiles
+    // start playing in step with the rest of their cells, and a replaced animated tile stops.
+    pub fn set_tile_gid(&mut self, layer: usize, xpos: usize, ypos: usize, raw_gid: u32) {
+        if layer >= self.layers.len() || xpos >= self.tilemap_size[0] || ypos >= self.tilemap_size[1] {
+            return
+        }
+        let (gid, flip) = split_gid(raw_gid);
+
+        let mut data = TileMapData::from_gid(&self.map_tilesets, raw_gid);
+        for animated in self.animated_tiles.iter_mut() {
+            animated.cells.retain(|&(l, x, y, _)| (l, x, y) != (layer, xpos, ypos));
+            if gid != 0 && animated.gid == gid {
+                animated.cells.push((layer, xpos, ypos, flip.bits()));
+                data = TileMapData::new(animated.frame_data(flip.bits()));
+            }
+        }
+        self.set_tile(layer, xpos, ypos, data.data);
+    }
+}
+
+// Writes a tile into a layer's cpu tiles, and into its plane when the tile is in the gpu window
+fn write_tile(layer: &mut TileMapLayer, tilemap_size: [usize; 2], focus: [usize; 2], charmap_size: [usize; 2], xpos: usize, ypos: usize, data: TileMapData) {
+    layer.tiles[(ypos * tilemap_size[0]) + xpos] = data;
+
+    if xpos >= focus[0] && xpos < focus[0] + charmap_size[0] &&
+        ypos >= focus[1] && ypos < focus[1] + charmap_size[1] {
+        let charmap_idx = ((ypos - focus[1]) * charmap_size[0]) + (xpos - focus[0]);
+        layer.tilemap_plane.set_data(charmap_idx, data);
     }
 }
 
 pub struct MapDrawPass {
-    projection: gfx::handle::Buffer<gfx_types::Resources, ProjectionStuff>,
-    tilemap_stuff: gfx::handle::Buffer<gfx_types::Resources, TilemapStuff>,
-    tilemap_data: gfx::handle::Buffer<gfx_types::Resources, TileMapData>,
-    tilesheet_sampler: gfx::handle::Sampler<gfx_types::Resources>,
-    tilemap: &'static TileMap,
+    tilemap: Shared<TileMap>,
+    // indices of the tile layers this pass draws, back to front
+    layers: Vec<usize>,
     pso: gfx::PipelineState<gfx_types::Resources, pipe::Meta>,
 }
 
@@ -357,27 +591,21 @@ impl Debug for MapDrawPass {
 impl PassDescription for MapDrawPass {}
 
 impl MapDrawPass {
-    pub fn new<F>(tilemap: &'static TileMap, factory: &mut F) -> MapDrawPass
+    // Draws only the given layers, in that order, so entities can be drawn between two passes
+    pub fn new<F>(tilemap: Shared<TileMap>, layers: Vec<usize>, factory: &mut F) -> Result<MapDrawPass>
         where F: gfx::Factory<gfx_types::Resources>
     {
-        let sampler = factory.create_sampler(
-            gfx::texture::SamplerInfo::new(
-                gfx::texture::FilterMethod::Scale,
-                gfx::texture::WrapMode::Clamp
-            )
-        );
-
         let vert_src = include_bytes!("shader/tilemap_150.glslv");
         let frag_src = include_bytes!("shader/tilemap_150.glslf");
 
-        MapDrawPass {
-            projection: factory.create_constant_buffer(1),
-            tilemap_stuff: factory.create_constant_buffer(1),
-            tilemap_data: factory.create_constant_buffer(1),
-            tilesheet_sampler: sampler,
+        let pso = try!(factory.create_pipeline_simple(vert_src, frag_src, pipe::new())
+            .map_err(|e| Error::gpu("tilemap pipeline", e)));
+
+        Ok(MapDrawPass {
             tilemap: tilemap,
-            pso: factory.create_pipeline_simple(vert_src, frag_src, pipe::new()).unwrap(),
-        }
+            layers: layers,
+            pso: pso,
+        })
     }
 }
 
@@ -393,17 +621,80 @@ impl Pass<gfx_types::Resources> for MapDrawPass {
         encoder: &mut gfx::Encoder<gfx_types::Resources, C>)
     where C: gfx::CommandBuffer<gfx_types::Resources>
     {
-        encoder.update_constant_buffer(&self.projection, &ProjectionStuff {
-            // use identity matrix until i figure out how i want to do map transforms
-            model: [[1f32, 0f32, 0f32, 0f32], [0f32, 1f32, 0f32, 0f32], [0f32, 0f32, 1f32, 0f32], [0f32, 0f32, 0f32, 1f32]],
-            proj: scene.camera.proj,
-            view: scene.camera.view,
-        });
+        let tilemap = self.tilemap.lock().unwrap();
+
+        for &idx in self.layers.iter() {
+            let layer = match tilemap.layers.get(idx) {
+                Some(layer) => layer,
+                None => continue,
+            };
+            if !layer.visible {
+                continue
+            }
+            layer.tilemap_plane.prepare_buffers(encoder, scene.camera.proj, scene.camera.view);
+            encoder.draw(&layer.tilemap_plane.slice, &self.pso, &layer.tilemap_plane.params);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_covers_partly_scrolled_tiles() {
+        assert_eq!(window_tiles([640.0, 480.0], 32.0, [100, 100]), [21, 16]);
+        // a view that's not a whole number of tiles rounds up before the extra one
+        assert_eq!(window_tiles([650.0, 490.0], 32.0, [100, 100]), [22, 17]);
+    }
 
-        let tilemap = self.tilemap;
+    #[test]
+    fn window_is_capped_to_the_map() {
+        assert_eq!(window_tiles([640.0, 480.0], 32.0, [10, 100]), [10, 16]);
+        assert_eq!(window_tiles([640.0, 480.0], 32.0, [10, 8]), [10, 8]);
+    }
+
+    #[test]
+    fn scrolling_moves_the_window_a_tile_at_a_time() {
+        // 640x480 view over a 100x50 map, the window is 21x16 tiles
+        let limit = [100 - 21, 50 - 16];
+        assert_eq!(scroll_window([0.0, 0.0], [640.0, 480.0], 32.0, [100, 50], limit), ([0.0, 0.0], [0, 0]));
+        assert_eq!(scroll_window([31.0, 33.0], [640.0, 480.0], 32.0, [100, 50], limit), ([31.0, 33.0], [0, 1]));
+        assert_eq!(scroll_window([320.0, 64.5], [640.0, 480.0], 32.0, [100, 50], limit), ([320.0, 64.5], [10, 2]));
+    }
 
-        tilemap.tilemap_plane.prepare_buffers(encoder, self.tilemap.focus_dirty);
+    #[test]
+    fn scrolling_stops_at_the_map_edges() {
+        let limit = [100 - 21, 50 - 16];
+        let (clamped, focus) = scroll_window([-50.0, 5000.0], [640.0, 480.0], 32.0, [100, 50], limit);
+        assert_eq!(clamped, [0.0, 1600.0 - 480.0]);
+        assert_eq!(focus, [0, 34]);
 
-        encoder.draw(&tilemap.tilemap_plane.slice, &self.pso, &tilemap.tilemap_plane.params);
+        let (clamped, focus) = scroll_window([9999.0, -1.0], [640.0, 480.0], 32.0, [100, 50], limit);
+        assert_eq!(clamped, [3200.0 - 640.0, 0.0]);
+        assert_eq!(focus, [79, 0]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn maps_smaller_than_the_view_dont_scroll() {
+        let (clamped, focus) = scroll_window([100.0, 100.0], [640.0, 480.0], 32.0, [10, 8], [0, 0]);
+        assert_eq!((clamped, focus), ([0.0, 0.0], [0, 0]));
+    }
+
+    #[test]
+    fn fit_view_keeps_views_that_fit() {
+        assert_eq!(fit_view([1920.0, 1080.0], 32.0, [100, 100]), (1, [1920.0, 1080.0]));
+        // a small map caps the window, so any view fits
+        assert_eq!(fit_view([3840.0, 2160.0], 32.0, [30, 20]), (1, [3840.0, 2160.0]));
+    }
+
+    #[test]
+    fn fit_view_zooms_views_too_big_for_the_buffer() {
+        let (scale, view_size) = fit_view([3840.0, 2160.0], 32.0, [200, 200]);
+        assert_eq!((scale, view_size), (2, [1920.0, 1080.0]));
+        let tiles = window_tiles(view_size, 32.0, [200, 200]);
+        assert!(tiles[0] * tiles[1] <= TILEMAP_BUF_LENGTH);
+
+        assert_eq!(fit_view([7680.0, 4320.0], 16.0, [1000, 1000]).0, 8);
+    }
+}
@@ -0,0 +1,192 @@
+extern crate tiled;
+
+// Tiled keeps the flip state of a placed tile in the top three bits of its gid
+pub const FLIPPED_HORIZONTALLY_FLAG: u32 = 0x80000000;
+pub const FLIPPED_VERTICALLY_FLAG: u32 = 0x40000000;
+pub const FLIPPED_DIAGONALLY_FLAG: u32 = 0x20000000;
+const ALL_FLIP_FLAGS: u32 = FLIPPED_HORIZONTALLY_FLAG | FLIPPED_VERTICALLY_FLAG | FLIPPED_DIAGONALLY_FLAG;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TileFlip {
+    pub horizontal: bool,
+    pub vertical: bool,
+    pub diagonal: bool,
+}
+
+impl TileFlip {
+    // packed the way the tilemap shader reads them: h = 4, v = 2, d = 1
+    pub fn bits(&self) -> f32 {
+        let mut bits = 0;
+        if self.horizontal {
+            bits |= 4;
+        }
+        if self.vertical {
+            bits |= 2;
+        }
+        if self.diagonal {
+            bits |= 1;
+        }
+        bits as f32
+    }
+}
+
+// splits a gid as stored in the layer data into the real gid and its flip flags
+pub fn split_gid(raw_gid: u32) -> (u32, TileFlip) {
+    let flip = TileFlip {
+        horizontal: raw_gid & FLIPPED_HORIZONTALLY_FLAG != 0,
+        vertical: raw_gid & FLIPPED_VERTICALLY_FLAG != 0,
+        diagonal: raw_gid & FLIPPED_DIAGONALLY_FLAG != 0,
+    };
+    (raw_gid & !ALL_FLIP_FLAGS, flip)
+}
+
+// Where a single gid lives in its tileset image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TileCoords {
+    pub tileset: usize,
+    pub column: u32,
+    pub row: u32,
+    // pixel position of the tile's top left corner in the tileset image
+    pub pixel: [f32; 2],
+    // [left, top, right, bottom] in 0..1 texture space
+    pub uv: [f32; 4],
+}
+
+// Tilesets own consecutive gid ranges starting at their first_gid, so the owner of a gid
+// is the tileset with the highest first_gid that is still <= gid
+pub fn tileset_for_gid(tilesets: &[tiled::Tileset], gid: u32) -> Option<usize> {
+    tilesets.iter()
+        .enumerate()
+        .filter(|&(_, tileset)| tileset.first_gid <= gid)
+        .max_by_key(|&(_, tileset)| tileset.first_gid)
+        .map(|(idx, _)| idx)
+}
+
+// tiled doesn't hand us the columns attribute, but it can be derived the same way Tiled does it
+pub fn tileset_columns(tileset: &tiled::Tileset) -> u32 {
+    let image = &tileset.images[0];
+    grid_cells(image.width as u32, tileset.tile_width, tileset.spacing, tileset.margin)
+}
+
+pub fn tileset_rows(tileset: &tiled::Tileset) -> u32 {
+    let image = &tileset.images[0];
+    grid_cells(image.height as u32, tileset.tile_height, tileset.spacing, tileset.margin)
+}
+
+fn grid_cells(image_size: u32, tile_size: u32, spacing: u32, margin: u32) -> u32 {
+    if image_size < margin * 2 + tile_size {
+        return 0
+    }
+    (image_size - margin * 2 + spacing) / (tile_size + spacing)
+}
+
+// column, row and top left pixel of a tileset local tile id
+fn tile_origin(local_id: u32, columns: u32, tile_size: [u32; 2], spacing: u32, margin: u32) -> (u32, u32, [f32; 2]) {
+    let column = local_id % columns;
+    let row = local_id / columns;
+    let x = margin + column * (tile_size[0] + spacing);
+    let y = margin + row * (tile_size[1] + spacing);
+    (column, row, [x as f32, y as f32])
+}
+
+pub fn gid_to_coords(tilesets: &[tiled::Tileset], gid: u32) -> Option<TileCoords> {
+    let (gid, _) = split_gid(gid);
+    if gid == 0 {
+        return None
+    }
+    let tileset_idx = match tileset_for_gid(tilesets, gid) {
+        Some(idx) => idx,
+        None => return None,
+    };
+    let tileset = &tilesets[tileset_idx];
+    let image = match tileset.images.get(0) {
+        Some(image) => image,
+        None => return None,
+    };
+
+    let columns = tileset_columns(tileset);
+    let local_id = gid - tileset.first_gid;
+    if columns == 0 || local_id >= columns * tileset_rows(tileset) {
+        return None
+    }
+
+    let (column, row, pixel) = tile_origin(
+        local_id, columns, [tileset.tile_width, tileset.tile_height], tileset.spacing, tileset.margin
+    );
+    let image_w = image.width as f32;
+    let image_h = image.height as f32;
+
+    Some(TileCoords {
+        tileset: tileset_idx,
+        column: column,
+        row: row,
+        pixel: pixel,
+        uv: [
+            pixel[0] / image_w,
+            pixel[1] / image_h,
+            (pixel[0] + tileset.tile_width as f32) / image_w,
+            (pixel[1] + tileset.tile_height as f32) / image_h,
+        ],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::path::Path;
+
+    fn load_map() -> tiled::Map {
+        let map_file = File::open(&Path::new("./resources/map.tmx")).unwrap();
+        tiled::parse(map_file).unwrap()
+    }
+
+    #[test]
+    fn empty_gid_has_no_coords() {
+        let map = load_map();
+        assert_eq!(gid_to_coords(&map.tilesets, 0), None);
+    }
+
+    #[test]
+    fn resolves_gids_in_map() {
+        let map = load_map();
+        assert_eq!(tileset_columns(&map.tilesets[0]), 2);
+
+        let first = gid_to_coords(&map.tilesets, 1).unwrap();
+        assert_eq!(first.tileset, 0);
+        assert_eq!((first.column, first.row), (0, 0));
+        assert_eq!(first.pixel, [0.0, 0.0]);
+        assert_eq!(first.uv, [0.0, 0.0, 0.5, 0.5]);
+
+        let second = gid_to_coords(&map.tilesets, 2).unwrap();
+        assert_eq!((second.column, second.row), (1, 0));
+        assert_eq!(second.pixel, [32.0, 0.0]);
+
+        let last = gid_to_coords(&map.tilesets, 4).unwrap();
+        assert_eq!((last.column, last.row), (1, 1));
+        assert_eq!(last.uv, [0.5, 0.5, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn gid_past_tileset_is_none() {
+        let map = load_map();
+        assert_eq!(gid_to_coords(&map.tilesets, 5), None);
+    }
+
+    #[test]
+    fn flip_flags_are_stripped() {
+        let map = load_map();
+        let raw = 3 | FLIPPED_HORIZONTALLY_FLAG | FLIPPED_DIAGONALLY_FLAG;
+        let (gid, flip) = split_gid(raw);
+        assert_eq!(gid, 3);
+        assert_eq!(flip, TileFlip { horizontal: true, vertical: false, diagonal: true });
+        assert_eq!(flip.bits(), 5.0);
+        assert_eq!(gid_to_coords(&map.tilesets, raw), gid_to_coords(&map.tilesets, 3));
+    }
+
+    #[test]
+    fn origin_accounts_for_spacing_and_margin() {
+        assert_eq!(grid_cells(104, 32, 2, 1), 3);
+        assert_eq!(tile_origin(4, 3, [32, 32], 2, 1), (1, 1, [35.0, 35.0]));
+    }
+}
@@ -0,0 +1,52 @@
+extern crate amethyst;
+
+use amethyst::ecs::{Join, RunArg, System};
+use amethyst::ecs::resources::Time;
+
+use entities::Player;
+use rendering::{Sprite, SpriteAnimation};
+use systems::duration_secs;
+
+// Steps every sprite animation and shows its current frame
+pub struct SpriteAnimator;
+
+impl System<()> for SpriteAnimator {
+    fn run(&mut self, arg: RunArg, _: ()) {
+        let (mut animations, mut sprites, time) = arg.fetch(|w| {
+            (w.write::<SpriteAnimation>(), w.write::<Sprite>(), w.read_resource::<Time>())
+        });
+
+        let delta = duration_secs(time.delta_time);
+        for (animation, sprite) in (&mut animations, &mut sprites).iter() {
+            if animation.advance(delta) {
+                if let Some(uv) = animation.uv() {
+                    sprite.uv = uv;
+                }
+            }
+        }
+    }
+}
+
+// Picks the player's clip from how it's moving. Movement has no gravity, so the player is
+// never in the air and the sheet's jump clip is left for when there's a jump to play it for.
+pub struct PlayerAnimation;
+
+impl System<()> for PlayerAnimation {
+    fn run(&mut self, arg: RunArg, _: ()) {
+        let (players, mut animations) = arg.fetch(|w| {
+            (w.read::<Player>(), w.write::<SpriteAnimation>())
+        });
+
+        for (player, animation) in (&players, &mut animations).iter() {
+            let clip = if player.velocity[0] != 0.0 || player.velocity[1] != 0.0 {
+                "run"
+            } else {
+                "idle"
+            };
+            // sheets don't have to define every clip
+            if !animation.play(clip) {
+                animation.play("idle");
+            }
+        }
+    }
+}
@@ -0,0 +1,163 @@
+extern crate amethyst;
+
+use amethyst::ecs::{Join, RunArg, System};
+use amethyst::ecs::resources::{Camera, Time};
+
+use entities::Player;
+use systems::duration_secs;
+
+// Where the camera is looking, in map pixels with y up
+pub struct CameraFocus {
+    pub position: [f32; 2],
+    // size of the map in pixels, the camera won't show past its edges. None for infinite maps
+    pub bounds: Option<[f32; 2]>,
+    // how much of the map the camera shows, the window size or the virtual resolution
+    pub view_size: [f32; 2],
+    // how far the camera has moved since the tilemap last caught up with it
+    pub scrolled: [f32; 2],
+}
+
+impl CameraFocus {
+    pub fn new(position: [f32; 2], bounds: Option<[f32; 2]>, view_size: [f32; 2]) -> CameraFocus {
+        CameraFocus {
+            position: position,
+            bounds: bounds,
+            view_size: view_size,
+            scrolled: [0.0, 0.0],
+        }
+    }
+
+    // hands over the pending scroll amount, for the tilemap to apply as offsets
+    pub fn take_scrolled(&mut self) -> [f32; 2] {
+        let scrolled = self.scrolled;
+        self.scrolled = [0.0, 0.0];
+        scrolled
+    }
+}
+
+pub struct CameraConfig {
+    // half the width and height of the box the player can move in without the camera following
+    pub deadzone: [f32; 2],
+    // seconds it takes the camera to cover most of the distance to the player, 0 to snap
+    pub smoothing: f32,
+}
+
+impl Default for CameraConfig {
+    fn default() -> CameraConfig {
+        CameraConfig {
+            deadzone: [64.0, 48.0],
+            smoothing: 0.15,
+        }
+    }
+}
+
+pub struct CameraFollow {
+    config: CameraConfig,
+}
+
+// keeps a half_view sized window around center inside 0..bound, centering it if the map is smaller
+fn clamp_axis(center: f32, half_view: f32, bound: f32) -> f32 {
+    if bound <= half_view * 2.0 {
+        bound / 2.0
+    } else {
+        center.max(half_view).min(bound - half_view)
+    }
+}
+
+impl CameraFollow {
+    pub fn new(config: CameraConfig) -> CameraFollow {
+        CameraFollow { config: config }
+    }
+
+    fn desired_axis(&self, current: f32, player: f32, deadzone: f32) -> f32 {
+        if player > current + deadzone {
+            player - deadzone
+        } else if player < current - deadzone {
+            player + deadzone
+        } else {
+            current
+        }
+    }
+}
+
+impl System<()> for CameraFollow {
+    fn run(&mut self, arg: RunArg, _: ()) {
+        let (players, mut camera, mut focus, time) = arg.fetch(|w| {
+            (w.read::<Player>(), w.write_resource::<Camera>(), w.write_resource::<CameraFocus>(), w.read_resource::<Time>())
+        });
+
+        let player = match players.iter().next() {
+            Some(player) => player,
+            None => return,
+        };
+
+        let delta = duration_secs(time.delta_time);
+        let current = focus.position;
+        let desired = [
+            self.desired_axis(current[0], player.position[0], self.config.deadzone[0]),
+            self.desired_axis(current[1], player.position[1], self.config.deadzone[1]),
+        ];
+
+        let t = if self.config.smoothing <= 0.0 {
+            1.0
+        } else {
+            1.0 - (-delta / self.config.smoothing).exp()
+        };
+
+        let mut position = [
+            current[0] + (desired[0] - current[0]) * t,
+            current[1] + (desired[1] - current[1]) * t,
+        ];
+        if let Some(bounds) = focus.bounds {
+            position[0] = clamp_axis(position[0], focus.view_size[0] / 2.0, bounds[0]);
+            position[1] = clamp_axis(position[1], focus.view_size[1] / 2.0, bounds[1]);
+        }
+
+        focus.scrolled[0] += position[0] - current[0];
+        focus.scrolled[1] += position[1] - current[1];
+        focus.position = position;
+
+        camera.eye = [position[0], position[1], 0.1];
+        camera.target = [position[0], position[1], 0.0];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clamp_keeps_the_view_inside_the_map() {
+        assert_eq!(clamp_axis(500.0, 200.0, 960.0), 500.0);
+        assert_eq!(clamp_axis(50.0, 200.0, 960.0), 200.0);
+        assert_eq!(clamp_axis(900.0, 200.0, 960.0), 760.0);
+    }
+
+    #[test]
+    fn clamp_centres_maps_smaller_than_the_view() {
+        assert_eq!(clamp_axis(50.0, 400.0, 640.0), 320.0);
+        assert_eq!(clamp_axis(600.0, 320.0, 640.0), 320.0);
+    }
+
+    #[test]
+    fn desired_stays_put_inside_the_deadzone() {
+        let follow = CameraFollow::new(CameraConfig::default());
+        assert_eq!(follow.desired_axis(100.0, 140.0, 64.0), 100.0);
+        assert_eq!(follow.desired_axis(100.0, 36.0, 64.0), 100.0);
+    }
+
+    #[test]
+    fn desired_follows_to_the_deadzone_edge() {
+        let follow = CameraFollow::new(CameraConfig::default());
+        assert_eq!(follow.desired_axis(100.0, 200.0, 64.0), 136.0);
+        assert_eq!(follow.desired_axis(100.0, 0.0, 64.0), 64.0);
+    }
+
+    #[test]
+    fn taking_scrolled_resets_it() {
+        let mut focus = CameraFocus::new([0.0, 0.0], None, [640.0, 480.0]);
+        focus.scrolled = [3.0, -2.0];
+        assert_eq!(focus.take_scrolled(), [3.0, -2.0]);
+        assert_eq!(focus.take_scrolled(), [0.0, 0.0]);
+    }
+}
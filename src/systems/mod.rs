@@ -0,0 +1,15 @@
+use std::time::Duration;
+
+mod animation;
+mod camera;
+mod movement;
+mod portal;
+
+pub use self::animation::*;
+pub use self::camera::*;
+pub use self::movement::*;
+pub use self::portal::*;
+
+pub fn duration_secs(duration: Duration) -> f32 {
+    duration.as_secs() as f32 + duration.subsec_nanos() as f32 / 1.0e9
+}
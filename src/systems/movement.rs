@@ -0,0 +1,89 @@
+extern crate amethyst;
+
+use amethyst::VirtualKeyCode;
+use amethyst::ecs::{Join, RunArg, System};
+use amethyst::ecs::components::LocalTransform;
+use amethyst::ecs::resources::{InputHandler, Time};
+
+use collision::{sweep_aabb, CollisionMap, Contacts};
+use entities::{Player, PLAYER_SIZE};
+use systems::duration_secs;
+
+// Keys and speed the player moves with
+pub struct MovementConfig {
+    pub left: VirtualKeyCode,
+    pub right: VirtualKeyCode,
+    pub up: VirtualKeyCode,
+    pub down: VirtualKeyCode,
+    // pixels per second
+    pub speed: f32,
+}
+
+impl Default for MovementConfig {
+    fn default() -> MovementConfig {
+        MovementConfig {
+            left: VirtualKeyCode::Left,
+            right: VirtualKeyCode::Right,
+            up: VirtualKeyCode::Up,
+            down: VirtualKeyCode::Down,
+            speed: 200.0,
+        }
+    }
+}
+
+pub struct PlayerMovement {
+    config: MovementConfig,
+}
+
+impl PlayerMovement {
+    pub fn new(config: MovementConfig) -> PlayerMovement {
+        PlayerMovement { config: config }
+    }
+
+    fn input_direction(&self, input: &InputHandler) -> [f32; 2] {
+        let mut direction = [0.0, 0.0];
+        if input.key_down(self.config.left) {
+            direction[0] -= 1.0;
+        }
+        if input.key_down(self.config.right) {
+            direction[0] += 1.0;
+        }
+        if input.key_down(self.config.down) {
+            direction[1] -= 1.0;
+        }
+        if input.key_down(self.config.up) {
+            direction[1] += 1.0;
+        }
+        direction
+    }
+}
+
+impl System<()> for PlayerMovement {
+    fn run(&mut self, arg: RunArg, _: ()) {
+        let (mut players, mut contacts, mut locals, input, time, collision_map) = arg.fetch(|w| {
+            (w.write::<Player>(), w.write::<Contacts>(), w.write::<LocalTransform>(),
+                w.read_resource::<InputHandler>(), w.read_resource::<Time>(), w.read_resource::<CollisionMap>())
+        });
+
+        let delta = duration_secs(time.delta_time);
+        let direction = self.input_direction(&input);
+
+        for (player, contact, local) in (&mut players, &mut contacts, &mut locals).iter() {
+            player.velocity = [direction[0] * self.config.speed, direction[1] * self.config.speed];
+            let movement = [player.velocity[0] * delta, player.velocity[1] * delta];
+            let (position, new_contacts) = sweep_aabb(&collision_map, player.position, PLAYER_SIZE, movement);
+
+            // don't keep pushing into whatever we ran into
+            if (new_contacts.wall_left && player.velocity[0] < 0.0) || (new_contacts.wall_right && player.velocity[0] > 0.0) {
+                player.velocity[0] = 0.0;
+            }
+            if (new_contacts.grounded && player.velocity[1] < 0.0) || (new_contacts.ceiling && player.velocity[1] > 0.0) {
+                player.velocity[1] = 0.0;
+            }
+            player.position = position;
+            *contact = new_contacts;
+
+            local.translation = [player.position[0], player.position[1], 0.0];
+        }
+    }
+}
@@ -0,0 +1,44 @@
+extern crate amethyst;
+
+use amethyst::ecs::{Join, RunArg, System};
+
+use entities::{Player, Portal, Trigger};
+
+// The portal the player walked into, for the game to take them through
+pub struct EnteredPortal(pub Option<Portal>);
+
+// Watches for the player walking into a portal. Only stepping in counts, so arriving on top
+// of one after a transition doesn't send the player straight back.
+pub struct EnterPortals {
+    inside: bool,
+}
+
+impl EnterPortals {
+    pub fn new() -> EnterPortals {
+        EnterPortals { inside: true }
+    }
+}
+
+impl System<()> for EnterPortals {
+    fn run(&mut self, arg: RunArg, _: ()) {
+        let (players, triggers, portals, mut entered) = arg.fetch(|w| {
+            (w.read::<Player>(), w.read::<Trigger>(), w.read::<Portal>(), w.write_resource::<EnteredPortal>())
+        });
+
+        let player = match players.iter().next() {
+            Some(player) => player,
+            None => return,
+        };
+
+        let touching = (&triggers, &portals).iter()
+            .find(|&(trigger, _)| trigger.contains(player.position))
+            .map(|(_, portal)| portal);
+
+        if let Some(portal) = touching {
+            if !self.inside {
+                entered.0 = Some(portal.clone());
+            }
+        }
+        self.inside = touching.is_some();
+    }
+}
@@ -0,0 +1,54 @@
+// A tile gameplay wants changed, eg a wall broken or a door opened
+#[derive(Clone, Debug, PartialEq)]
+pub struct TileEdit {
+    // name of the tile layer in Tiled
+    pub layer: String,
+    // in tiles, rows counting bottom up like the tilemap and collision
+    pub position: [usize; 2],
+    // gid with Tiled's flip flags, 0 for no tile
+    pub gid: u32,
+}
+
+// Tile changes queued up by systems. A world resource, the game applies them to the map, the
+// tilemap on the gpu and the collision map together each update.
+pub struct TileEdits {
+    edits: Vec<TileEdit>,
+}
+
+impl TileEdits {
+    pub fn new() -> TileEdits {
+        TileEdits { edits: Vec::new() }
+    }
+
+    // gid 0 removes the tile
+    pub fn set(&mut self, layer: &str, position: [usize; 2], gid: u32) {
+        self.edits.push(TileEdit {
+            layer: layer.to_string(),
+            position: position,
+            gid: gid,
+        });
+    }
+
+    // hands over everything queued, oldest first
+    pub fn take(&mut self) -> Vec<TileEdit> {
+        ::std::mem::replace(&mut self.edits, Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hands_over_edits_in_order_once() {
+        let mut edits = TileEdits::new();
+        edits.set("walls", [3, 4], 12);
+        edits.set("walls", [3, 4], 0);
+
+        let taken = edits.take();
+        assert_eq!(taken.len(), 2);
+        assert_eq!(taken[0], TileEdit { layer: "walls".to_string(), position: [3, 4], gid: 12 });
+        assert_eq!(taken[1].gid, 0);
+        assert!(edits.take().is_empty());
+    }
+}
@@ -0,0 +1,171 @@
+extern crate xml;
+
+use std::io::Read;
+
+use xml::attribute::OwnedAttribute;
+use xml::reader::{EventReader, XmlEvent};
+
+use tmx::decode_gids;
+
+// A block of tiles from an infinite map layer. x and y are in tiles, in Tiled's
+// coordinates (y going down, can be negative)
+pub struct Chunk {
+    pub x: i32,
+    pub y: i32,
+    pub width: usize,
+    pub height: usize,
+    // rows top down, same as Tiled writes them
+    pub gids: Vec<u32>,
+}
+
+impl Chunk {
+    // the chunk's lowest row counting up from the bottom of the map, the way the game does
+    pub fn bottom_row(&self, map_height: u32) -> i32 {
+        map_height as i32 - (self.y + self.height as i32)
+    }
+}
+
+pub struct InfiniteLayer {
+    pub name: String,
+    pub visible: bool,
+    pub opacity: f32,
+    pub chunks: Vec<Chunk>,
+}
+
+pub fn attr<'a>(attributes: &'a [OwnedAttribute], name: &str) -> Option<&'a str> {
+    attributes.iter()
+        .find(|attribute| attribute.name.local_name == name)
+        .map(|attribute| attribute.value.as_str())
+}
+
+pub fn parse_attr<T: ::std::str::FromStr>(attributes: &[OwnedAttribute], name: &str, default: T) -> Result<T, String> {
+    match attr(attributes, name) {
+        Some(value) => value.parse().map_err(|_| format!("invalid {} attribute {:?}", name, value)),
+        None => Ok(default),
+    }
+}
+
+// tiled doesn't know about chunks, so the layer data of infinite maps is read here.
+// Returns None when the map isn't an infinite one.
+pub fn read_infinite_layers<R: Read>(reader: R) -> Result<Option<Vec<InfiniteLayer>>, String> {
+    let parser = EventReader::new(reader);
+    let mut layers = Vec::new();
+    let mut layer: Option<InfiniteLayer> = None;
+    let mut encoding: Option<String> = None;
+    let mut compression: Option<String> = None;
+    let mut chunk: Option<Chunk> = None;
+
+    for event in parser {
+        match try!(event.map_err(|e| format!("{}", e))) {
+            XmlEvent::StartElement { name, attributes, .. } => {
+                match name.local_name.as_str() {
+                    "map" => {
+                        if attr(&attributes, "infinite") != Some("1") {
+                            return Ok(None)
+                        }
+                    },
+                    "layer" => {
+                        layer = Some(InfiniteLayer {
+                            name: attr(&attributes, "name").unwrap_or("").to_string(),
+                            visible: try!(parse_attr::<u32>(&attributes, "visible", 1)) != 0,
+                            opacity: try!(parse_attr(&attributes, "opacity", 1.0)),
+                            chunks: Vec::new(),
+                        });
+                    },
+                    "data" => {
+                        encoding = attr(&attributes, "encoding").map(|e| e.to_string());
+                        compression = attr(&attributes, "compression").map(|c| c.to_string());
+                    },
+                    "chunk" => {
+                        chunk = Some(Chunk {
+                            x: try!(parse_attr(&attributes, "x", 0)),
+                            y: try!(parse_attr(&attributes, "y", 0)),
+                            width: try!(parse_attr(&attributes, "width", 0)),
+                            height: try!(parse_attr(&attributes, "height", 0)),
+                            gids: Vec::new(),
+                        });
+                    },
+                    _ => (),
+                }
+            },
+            XmlEvent::Characters(text) => {
+                if let Some(ref mut chunk) = chunk {
+                    chunk.gids = try!(decode_gids(&text, encoding.as_ref().map(|e| e.as_str()), compression.as_ref().map(|c| c.as_str())));
+                }
+            },
+            XmlEvent::EndElement { name } => {
+                match name.local_name.as_str() {
+                    "chunk" => {
+                        if let (Some(chunk), Some(layer)) = (chunk.take(), layer.as_mut()) {
+                            if chunk.gids.len() != chunk.width * chunk.height {
+                                return Err(format!("chunk at {}, {} has {} tiles, expected {}", chunk.x, chunk.y, chunk.gids.len(), chunk.width * chunk.height));
+                            }
+                            layer.chunks.push(chunk);
+                        }
+                    },
+                    "layer" => {
+                        if let Some(layer) = layer.take() {
+                            layers.push(layer);
+                        }
+                    },
+                    _ => (),
+                }
+            },
+            _ => (),
+        }
+    }
+
+    Ok(Some(layers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    fn fixture() -> Vec<InfiniteLayer> {
+        read_infinite_layers(File::open("./tests/fixtures/infinite.tmx").unwrap()).unwrap().unwrap()
+    }
+
+    #[test]
+    fn reads_csv_chunks() {
+        let layers = fixture();
+        assert_eq!(layers.len(), 2);
+        let ground = &layers[0];
+        assert_eq!(ground.name, "ground");
+        let positions: Vec<(i32, i32, usize, usize)> = ground.chunks.iter().map(|c| (c.x, c.y, c.width, c.height)).collect();
+        assert_eq!(positions, vec![(-4, 0, 4, 4), (0, 4, 4, 4)]);
+        assert_eq!(ground.chunks[0].gids, vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 2, 2, 2]);
+        assert_eq!(ground.chunks[1].gids[4..8].to_vec(), vec![3, 4, 4, 3]);
+    }
+
+    #[test]
+    fn reads_base64_zlib_chunks() {
+        let walls = &fixture()[1];
+        assert_eq!(walls.name, "walls");
+        assert_eq!(walls.opacity, 0.5);
+        assert!(walls.visible);
+        assert_eq!(walls.chunks.len(), 1);
+        assert_eq!(walls.chunks[0].gids, vec![1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 0, 1, 2, 2, 2, 1]);
+    }
+
+    #[test]
+    fn finite_maps_have_no_chunks() {
+        assert!(read_infinite_layers(File::open("./resources/map.tmx").unwrap()).unwrap().is_none());
+    }
+
+    #[test]
+    fn chunks_with_the_wrong_number_of_tiles_are_rejected() {
+        let data = r#"<map infinite="1"><layer name="ground"><data encoding="csv">
+            <chunk x="0" y="0" width="2" height="2">1,2,3</chunk>
+        </data></layer></map>"#;
+        assert!(read_infinite_layers(data.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn bottom_row_counts_up_from_the_bottom_of_the_map() {
+        let layers = fixture();
+        assert_eq!(layers[0].chunks[0].bottom_row(8), 4);
+        assert_eq!(layers[0].chunks[1].bottom_row(8), 0);
+    }
+}
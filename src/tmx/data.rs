@@ -0,0 +1,43 @@
+extern crate base64;
+extern crate flate2;
+
+use std::io::Read;
+
+use flate2::read::{GzDecoder, ZlibDecoder};
+
+fn bytes_to_gids(bytes: &[u8]) -> Vec<u32> {
+    bytes.chunks(4)
+        .filter(|gid| gid.len() == 4)
+        .map(|gid| gid[0] as u32 | (gid[1] as u32) << 8 | (gid[2] as u32) << 16 | (gid[3] as u32) << 24)
+        .collect()
+}
+
+// Decodes the text of a <data> or <chunk> element into a flat list of gids, rows top down
+pub fn decode_gids(text: &str, encoding: Option<&str>, compression: Option<&str>) -> Result<Vec<u32>, String> {
+    match encoding {
+        Some("csv") => {
+            text.split(',')
+                .map(|gid| gid.trim())
+                .filter(|gid| !gid.is_empty())
+                .map(|gid| gid.parse::<u32>().map_err(|e| format!("bad gid {:?} in csv data: {}", gid, e)))
+                .collect()
+        },
+        Some("base64") => {
+            let raw = try!(base64::u8de(text.trim().as_bytes()).map_err(|e| format!("invalid base64 data: {:?}", e)));
+            let mut bytes = Vec::new();
+            let read = match compression {
+                None => return Ok(bytes_to_gids(&raw)),
+                Some("zlib") => ZlibDecoder::new(&raw[..]).read_to_end(&mut bytes),
+                Some("gzip") => {
+                    let mut decoder = try!(GzDecoder::new(&raw[..]).map_err(|e| format!("invalid gzip data: {}", e)));
+                    decoder.read_to_end(&mut bytes)
+                },
+                Some(other) => return Err(format!("unsupported compression {:?}", other)),
+            };
+            try!(read.map_err(|e| format!("couldn't decompress tile data: {}", e)));
+            Ok(bytes_to_gids(&bytes))
+        },
+        Some(other) => Err(format!("unsupported encoding {:?}", other)),
+        None => Err("xml tile data isn't supported, save the map with csv or base64 encoding".to_string()),
+    }
+}
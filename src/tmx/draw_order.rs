@@ -0,0 +1,140 @@
+extern crate xml;
+
+use std::io::Read;
+
+use xml::reader::{EventReader, XmlEvent};
+
+use tmx::attr;
+
+// name of the object layer that marks where entity sprites are drawn among the tile layers
+pub const ENTITIES_LAYER: &'static str = "entities";
+
+// Where each tile layer and the entity sprites sit in the draw order, lowest first.
+// A layer's z is its float "z" property if it has one, otherwise its position in the document.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DrawOrder {
+    // in the same order as the map's tile layers
+    pub layer_z: Vec<f32>,
+    // above every layer when the map has no entities object layer
+    pub entities_z: f32,
+}
+
+impl DrawOrder {
+    // The tile layers drawn below and above the entities, each sorted back to front.
+    // Layers with the same z as the entities go below them.
+    pub fn split(&self) -> (Vec<usize>, Vec<usize>) {
+        let mut order: Vec<usize> = (0..self.layer_z.len()).collect();
+        order.sort_by(|&a, &b| self.layer_z[a].partial_cmp(&self.layer_z[b]).unwrap());
+        order.into_iter().partition(|&idx| self.layer_z[idx] <= self.entities_z)
+    }
+}
+
+// Tiled only keeps the order of tile and object layers in the document, which tiled splits into
+// separate lists, so it's read here
+pub fn read_draw_order<R: Read>(reader: R) -> Result<DrawOrder, String> {
+    let parser = EventReader::new(reader);
+    let mut layer_z = Vec::new();
+    let mut entities_z = None;
+    // every layer kind counts towards the document position, only tile layers and the entities layer are kept
+    let mut position = 0;
+    let mut elements: Vec<String> = Vec::new();
+    let mut current: Option<(bool, f32)> = None;
+
+    for event in parser {
+        match try!(event.map_err(|e| format!("{}", e))) {
+            XmlEvent::StartElement { name, attributes, .. } => {
+                let element = name.local_name;
+                let parent_is_map = elements.last().map(|e| e == "map").unwrap_or(false);
+                match element.as_str() {
+                    "layer" | "objectgroup" | "imagelayer" | "group" if parent_is_map => {
+                        let is_entities = element == "objectgroup" && attr(&attributes, "name") == Some(ENTITIES_LAYER);
+                        current = Some((element == "layer" || is_entities, position as f32));
+                        position += 1;
+                    },
+                    // only the layer's own properties, not those of the objects in it
+                    "property" if elements.len() == 3 && elements[2] == "properties" => {
+                        if let (Some("z"), Some(value)) = (attr(&attributes, "name"), attr(&attributes, "value")) {
+                            if let Some(layer) = current.as_mut() {
+                                // nan and infinity parse, but can't be put in order
+                                layer.1 = try!(value.parse::<f32>().ok()
+                                    .and_then(|z| if z.is_finite() { Some(z) } else { None })
+                                    .ok_or_else(|| format!("invalid z property {:?}", value)));
+                            }
+                        }
+                    },
+                    _ => (),
+                }
+                elements.push(element);
+            },
+            XmlEvent::EndElement { name } => {
+                elements.pop();
+                if elements.len() == 1 {
+                    if let Some((kept, z)) = current.take() {
+                        if kept && name.local_name == "layer" {
+                            layer_z.push(z);
+                        } else if kept {
+                            entities_z = Some(z);
+                        }
+                    }
+                }
+            },
+            _ => (),
+        }
+    }
+
+    Ok(DrawOrder {
+        layer_z: layer_z,
+        entities_z: entities_z.unwrap_or(position as f32),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    #[test]
+    fn entities_default_to_the_top() {
+        let order = read_draw_order(File::open("./resources/map.tmx").unwrap()).unwrap();
+        assert_eq!(order.layer_z, vec![0.0, 1.0]);
+        assert_eq!(order.split(), (vec![0, 1], vec![]));
+    }
+
+    #[test]
+    fn entities_layer_splits_tile_layers() {
+        let data = r#"<map>
+            <layer name="ground"><data/></layer>
+            <objectgroup name="entities">
+                <object><properties><property name="z" value="10"/></properties></object>
+            </objectgroup>
+            <layer name="canopy"><data/></layer>
+        </map>"#;
+        let order = read_draw_order(data.as_bytes()).unwrap();
+        assert_eq!(order.layer_z, vec![0.0, 2.0]);
+        assert_eq!(order.entities_z, 1.0);
+        assert_eq!(order.split(), (vec![0], vec![1]));
+    }
+
+    #[test]
+    fn z_property_overrides_document_order() {
+        let data = r#"<map>
+            <layer name="canopy"><properties><property name="z" value="5"/></properties><data/></layer>
+            <layer name="ground"><data/></layer>
+            <objectgroup name="entities"><properties><property name="z" value="2.5"/></properties></objectgroup>
+        </map>"#;
+        let order = read_draw_order(data.as_bytes()).unwrap();
+        assert_eq!(order.layer_z, vec![5.0, 1.0]);
+        assert_eq!(order.entities_z, 2.5);
+        assert_eq!(order.split(), (vec![1], vec![0]));
+    }
+
+    #[test]
+    fn z_must_be_a_finite_number() {
+        for value in &["nan", "inf", "-infinity", "high"] {
+            let data = format!(r#"<map>
+                <layer name="ground"><properties><property name="z" value="{}"/></properties><data/></layer>
+            </map>"#, value);
+            assert!(read_draw_order(data.as_bytes()).is_err(), "z of {:?} was accepted", value);
+        }
+    }
+}
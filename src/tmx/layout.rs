@@ -0,0 +1,114 @@
+extern crate xml;
+
+use std::collections::HashMap;
+use std::io::Read;
+
+use xml::reader::{EventReader, XmlEvent};
+
+use tmx::attr;
+
+// A tile layer or object layer by its index in map.layers or map.object_groups
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LayoutEntry {
+    Layer(usize),
+    ObjectGroup(usize),
+}
+
+// What tiled drops from a map that's needed to write it back the way it was read: the order
+// tile and object layers come in, object layer properties and the render order
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layout {
+    pub render_order: String,
+    pub entries: Vec<LayoutEntry>,
+    // in the same order as map.object_groups
+    pub group_properties: Vec<HashMap<String, String>>,
+}
+
+pub fn read_layout<R: Read>(reader: R) -> Result<Layout, String> {
+    let parser = EventReader::new(reader);
+    let mut render_order = "right-down".to_string();
+    let mut entries = Vec::new();
+    let mut group_properties = Vec::new();
+    let mut layers = 0;
+    let mut elements: Vec<String> = Vec::new();
+
+    for event in parser {
+        match try!(event.map_err(|e| format!("{}", e))) {
+            XmlEvent::StartElement { name, attributes, .. } => {
+                let element = name.local_name;
+                let parent_is_map = elements.last().map(|e| e == "map").unwrap_or(false);
+                match element.as_str() {
+                    "map" if elements.is_empty() => {
+                        if let Some(order) = attr(&attributes, "renderorder") {
+                            render_order = order.to_string();
+                        }
+                    },
+                    "layer" if parent_is_map => {
+                        entries.push(LayoutEntry::Layer(layers));
+                        layers += 1;
+                    },
+                    "objectgroup" if parent_is_map => {
+                        entries.push(LayoutEntry::ObjectGroup(group_properties.len()));
+                        group_properties.push(HashMap::new());
+                    },
+                    // only the object layer's own properties, not those of the objects in it
+                    "property" if elements.len() == 3 && elements[1] == "objectgroup" && elements[2] == "properties" => {
+                        if let (Some(name), Some(properties)) = (attr(&attributes, "name"), group_properties.last_mut()) {
+                            properties.insert(name.to_string(), attr(&attributes, "value").unwrap_or("").to_string());
+                        }
+                    },
+                    _ => (),
+                }
+                elements.push(element);
+            },
+            XmlEvent::EndElement { .. } => {
+                elements.pop();
+            },
+            _ => (),
+        }
+    }
+
+    Ok(Layout {
+        render_order: render_order,
+        entries: entries,
+        group_properties: group_properties,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_document_order_and_group_properties() {
+        let data = r#"<map renderorder="left-up">
+            <objectgroup name="entities">
+                <properties><property name="z" value="2.5"/></properties>
+                <object><properties><property name="kind" value="crate"/></properties></object>
+            </objectgroup>
+            <layer name="canopy"><data/></layer>
+            <imagelayer name="sky"/>
+            <layer name="ground"><data/></layer>
+            <objectgroup name="triggers"/>
+        </map>"#;
+        let layout = read_layout(data.as_bytes()).unwrap();
+        assert_eq!(layout.render_order, "left-up");
+        assert_eq!(layout.entries, vec![
+            LayoutEntry::ObjectGroup(0),
+            LayoutEntry::Layer(0),
+            LayoutEntry::Layer(1),
+            LayoutEntry::ObjectGroup(1),
+        ]);
+        assert_eq!(layout.group_properties.len(), 2);
+        assert_eq!(layout.group_properties[0].get("z").map(|z| z.as_str()), Some("2.5"));
+        assert!(layout.group_properties[0].get("kind").is_none());
+        assert!(layout.group_properties[1].is_empty());
+    }
+
+    #[test]
+    fn render_order_defaults_to_right_down() {
+        let layout = read_layout(r#"<map><layer name="ground"><data/></layer></map>"#.as_bytes()).unwrap();
+        assert_eq!(layout.render_order, "right-down");
+        assert_eq!(layout.entries, vec![LayoutEntry::Layer(0)]);
+    }
+}
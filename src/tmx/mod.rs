@@ -0,0 +1,11 @@
+mod chunks;
+mod data;
+mod draw_order;
+mod layout;
+mod writer;
+
+pub use self::chunks::*;
+pub use self::data::*;
+pub use self::draw_order::*;
+pub use self::layout::*;
+pub use self::writer::*;
@@ -0,0 +1,418 @@
+extern crate base64;
+extern crate flate2;
+extern crate tiled;
+
+use std::collections::HashMap;
+use std::io;
+use std::io::Write;
+
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+
+use tmx::{Layout, LayoutEntry};
+
+// what a saved map's extension is replaced with, the hot reload watcher leaves these alone
+pub const SAVED_EXTENSION: &'static str = "saved.tmx";
+
+// How tile layer data gets written, both can be read back by Tiled and by decode_gids
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DataEncoding {
+    Csv,
+    Base64Zlib,
+}
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn colour(colour: &tiled::Colour) -> String {
+    format!("#{:02x}{:02x}{:02x}", colour.red, colour.green, colour.blue)
+}
+
+fn orientation(orientation: &tiled::Orientation) -> &'static str {
+    match *orientation {
+        tiled::Orientation::Orthogonal => "orthogonal",
+        tiled::Orientation::Isometric => "isometric",
+        tiled::Orientation::Staggered => "staggered",
+    }
+}
+
+// Rows top down, the way Tiled lays them out, one row per line
+fn encode_csv(tiles: &[Vec<u32>]) -> String {
+    let rows: Vec<String> = tiles.iter()
+        .map(|row| row.iter().map(|gid| gid.to_string()).collect::<Vec<_>>().join(","))
+        .collect();
+    format!("\n{}\n", rows.join(",\n"))
+}
+
+// Little endian gids, zlib compressed and base64 encoded, the inverse of decode_gids
+fn encode_base64_zlib(tiles: &[Vec<u32>]) -> io::Result<String> {
+    let mut bytes = Vec::new();
+    for gid in tiles.iter().flat_map(|row| row.iter()) {
+        bytes.extend_from_slice(&[*gid as u8, (*gid >> 8) as u8, (*gid >> 16) as u8, (*gid >> 24) as u8]);
+    }
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::Default);
+    try!(encoder.write_all(&bytes));
+    let compressed = try!(encoder.finish());
+    let encoded = try!(base64::u8en(&compressed).map_err(|e| io::Error::new(io::ErrorKind::Other, format!("{:?}", e))));
+    String::from_utf8(encoded).map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+}
+
+// Writes elements one per line, indented by a space per level like Tiled does
+struct TmxWriter<W: Write> {
+    out: W,
+    depth: usize,
+}
+
+impl<W: Write> TmxWriter<W> {
+    fn tag(&mut self, name: &str, attributes: &[(&str, String)], empty: bool) -> io::Result<()> {
+        try!(write!(self.out, "{:1$}<{2}", "", self.depth, name));
+        for &(key, ref value) in attributes.iter() {
+            try!(write!(self.out, " {}=\"{}\"", key, escape(value)));
+        }
+        if empty {
+            writeln!(self.out, "/>")
+        } else {
+            self.depth += 1;
+            writeln!(self.out, ">")
+        }
+    }
+
+    fn open(&mut self, name: &str, attributes: &[(&str, String)]) -> io::Result<()> {
+        self.tag(name, attributes, false)
+    }
+
+    fn empty(&mut self, name: &str, attributes: &[(&str, String)]) -> io::Result<()> {
+        self.tag(name, attributes, true)
+    }
+
+    fn close(&mut self, name: &str) -> io::Result<()> {
+        self.depth -= 1;
+        writeln!(self.out, "{:1$}</{2}>", "", self.depth, name)
+    }
+
+    // sorted by name, so saving the same map twice gives the same file
+    fn properties(&mut self, properties: &HashMap<String, String>) -> io::Result<()> {
+        if properties.is_empty() {
+            return Ok(())
+        }
+        let mut names: Vec<&String> = properties.keys().collect();
+        names.sort();
+
+        try!(self.open("properties", &[]));
+        for name in names {
+            try!(self.empty("property", &[("name", name.clone()), ("value", properties[name].clone())]));
+        }
+        self.close("properties")
+    }
+
+    fn tileset(&mut self, tileset: &tiled::Tileset) -> io::Result<()> {
+        let mut attributes = vec![
+            ("firstgid", tileset.first_gid.to_string()),
+            ("name", tileset.name.clone()),
+            ("tilewidth", tileset.tile_width.to_string()),
+            ("tileheight", tileset.tile_height.to_string()),
+        ];
+        if tileset.spacing != 0 {
+            attributes.push(("spacing", tileset.spacing.to_string()));
+        }
+        if tileset.margin != 0 {
+            attributes.push(("margin", tileset.margin.to_string()));
+        }
+        try!(self.open("tileset", &attributes));
+
+        for image in tileset.images.iter() {
+            try!(self.image(image));
+        }
+        for tile in tileset.tiles.iter() {
+            try!(self.open("tile", &[("id", tile.id.to_string())]));
+            try!(self.properties(&tile.properties));
+            if let Some(ref group) = tile.objectgroup {
+                try!(self.object_group(group, &HashMap::new()));
+            }
+            if let Some(ref frames) = tile.animation {
+                try!(self.open("animation", &[]));
+                for frame in frames.iter() {
+                    try!(self.empty("frame", &[("tileid", frame.tile_id.to_string()), ("duration", frame.duration.to_string())]));
+                }
+                try!(self.close("animation"));
+            }
+            try!(self.close("tile"));
+        }
+        self.close("tileset")
+    }
+
+    fn image(&mut self, image: &tiled::Image) -> io::Result<()> {
+        let mut attributes = vec![("source", image.source.clone())];
+        if let Some(ref trans) = image.transparent_colour {
+            // Tiled writes this one without the #
+            attributes.push(("trans", colour(trans)[1..].to_string()));
+        }
+        attributes.push(("width", image.width.to_string()));
+        attributes.push(("height", image.height.to_string()));
+        self.empty("image", &attributes)
+    }
+
+    fn layer(&mut self, layer: &tiled::Layer, size: [u32; 2], encoding: DataEncoding) -> io::Result<()> {
+        let mut attributes = vec![
+            ("name", layer.name.clone()),
+            ("width", size[0].to_string()),
+            ("height", size[1].to_string()),
+        ];
+        if layer.opacity != 1.0 {
+            attributes.push(("opacity", layer.opacity.to_string()));
+        }
+        if !layer.visible {
+            attributes.push(("visible", "0".to_string()));
+        }
+        try!(self.open("layer", &attributes));
+        try!(self.properties(&layer.properties));
+
+        let (attributes, text) = match encoding {
+            DataEncoding::Csv => (vec![("encoding", "csv".to_string())], encode_csv(&layer.tiles)),
+            DataEncoding::Base64Zlib => {
+                let text = try!(encode_base64_zlib(&layer.tiles));
+                (vec![("encoding", "base64".to_string()), ("compression", "zlib".to_string())], text)
+            },
+        };
+        // the data goes straight after the tag so no whitespace ends up in base64
+        try!(write!(self.out, "{:1$}<data", "", self.depth));
+        for &(key, ref value) in attributes.iter() {
+            try!(write!(self.out, " {}=\"{}\"", key, value));
+        }
+        try!(writeln!(self.out, ">{}</data>", text));
+        self.close("layer")
+    }
+
+    fn object_group(&mut self, group: &tiled::ObjectGroup, properties: &HashMap<String, String>) -> io::Result<()> {
+        let mut attributes = vec![("name", group.name.clone())];
+        if let Some(ref group_colour) = group.colour {
+            attributes.push(("color", colour(group_colour)));
+        }
+        if group.opacity != 1.0 {
+            attributes.push(("opacity", group.opacity.to_string()));
+        }
+        if !group.visible {
+            attributes.push(("visible", "0".to_string()));
+        }
+        try!(self.open("objectgroup", &attributes));
+        try!(self.properties(properties));
+        for object in group.objects.iter() {
+            try!(self.object(object));
+        }
+        self.close("objectgroup")
+    }
+
+    fn object(&mut self, object: &tiled::Object) -> io::Result<()> {
+        let mut attributes = vec![("id", object.id.to_string())];
+        if !object.name.is_empty() {
+            attributes.push(("name", object.name.clone()));
+        }
+        if !object.obj_type.is_empty() {
+            attributes.push(("type", object.obj_type.clone()));
+        }
+        if object.gid != 0 {
+            attributes.push(("gid", object.gid.to_string()));
+        }
+        attributes.push(("x", object.x.to_string()));
+        attributes.push(("y", object.y.to_string()));
+        match object.shape {
+            tiled::ObjectShape::Rect { width, height } | tiled::ObjectShape::Ellipse { width, height } => {
+                attributes.push(("width", width.to_string()));
+                attributes.push(("height", height.to_string()));
+            },
+            _ => (),
+        }
+        if object.rotation != 0.0 {
+            attributes.push(("rotation", object.rotation.to_string()));
+        }
+        if !object.visible {
+            attributes.push(("visible", "0".to_string()));
+        }
+
+        let points = |points: &[(f32, f32)]| {
+            points.iter().map(|&(x, y)| format!("{},{}", x, y)).collect::<Vec<_>>().join(" ")
+        };
+        let shape = match object.shape {
+            tiled::ObjectShape::Rect { .. } => None,
+            tiled::ObjectShape::Ellipse { .. } => Some(("ellipse", Vec::new())),
+            tiled::ObjectShape::Polygon { points: ref p } => Some(("polygon", vec![("points", points(p))])),
+            tiled::ObjectShape::Polyline { points: ref p } => Some(("polyline", vec![("points", points(p))])),
+        };
+
+        if object.properties.is_empty() && shape.is_none() {
+            return self.empty("object", &attributes)
+        }
+        try!(self.open("object", &attributes));
+        try!(self.properties(&object.properties));
+        if let Some((name, attributes)) = shape {
+            try!(self.empty(name, &attributes));
+        }
+        self.close("object")
+    }
+}
+
+// Saves a map as TMX that Tiled and tiled::parse can open again. Tilesets are embedded in the
+// map. tiled keeps tile and object layers apart, layout puts them back in the order they were
+// read in. Infinite maps' chunks aren't kept by tiled, so they can't be written.
+pub fn write_map<W: Write>(map: &tiled::Map, layout: &Layout, encoding: DataEncoding, out: W) -> io::Result<()> {
+    let mut writer = TmxWriter { out: out, depth: 0 };
+
+    let next_object_id = map.object_groups.iter()
+        .flat_map(|group| group.objects.iter())
+        .map(|object| object.id + 1)
+        .max()
+        .unwrap_or(1);
+
+    try!(writeln!(writer.out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>"));
+    let mut attributes = vec![
+        ("version", map.version.clone()),
+        ("orientation", orientation(&map.orientation).to_string()),
+        ("renderorder", layout.render_order.clone()),
+        ("width", map.width.to_string()),
+        ("height", map.height.to_string()),
+        ("tilewidth", map.tile_width.to_string()),
+        ("tileheight", map.tile_height.to_string()),
+    ];
+    if let Some(ref background) = map.background_colour {
+        attributes.push(("backgroundcolor", colour(background)));
+    }
+    attributes.push(("nextobjectid", next_object_id.to_string()));
+    try!(writer.open("map", &attributes));
+    try!(writer.properties(&map.properties));
+
+    for tileset in map.tilesets.iter() {
+        try!(writer.tileset(tileset));
+    }
+
+    let no_properties = HashMap::new();
+    let group_properties = |idx: usize| layout.group_properties.get(idx).unwrap_or(&no_properties);
+    for entry in layout.entries.iter() {
+        match *entry {
+            LayoutEntry::Layer(idx) => if let Some(layer) = map.layers.get(idx) {
+                try!(writer.layer(layer, [map.width, map.height], encoding));
+            },
+            LayoutEntry::ObjectGroup(idx) => if let Some(group) = map.object_groups.get(idx) {
+                try!(writer.object_group(group, group_properties(idx)));
+            },
+        }
+    }
+    // anything the layout doesn't know about goes last
+    let layers_seen = layout.entries.iter().filter(|entry| match **entry { LayoutEntry::Layer(_) => true, _ => false }).count();
+    for layer in map.layers.iter().skip(layers_seen) {
+        try!(writer.layer(layer, [map.width, map.height], encoding));
+    }
+    for (idx, group) in map.object_groups.iter().enumerate().skip(layout.group_properties.len()) {
+        try!(writer.object_group(group, group_properties(idx)));
+    }
+
+    writer.close("map")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use tmx::{decode_gids, read_draw_order, read_layout};
+
+    fn round_trip(encoding: DataEncoding) {
+        let map = tiled::parse(File::open("./resources/map.tmx").unwrap()).unwrap();
+        let layout = read_layout(File::open("./resources/map.tmx").unwrap()).unwrap();
+        let draw_order = read_draw_order(File::open("./resources/map.tmx").unwrap()).unwrap();
+
+        let mut saved = Vec::new();
+        write_map(&map, &layout, encoding, &mut saved).unwrap();
+        let reloaded = tiled::parse(&saved[..]).unwrap();
+
+        assert_eq!((reloaded.width, reloaded.height), (map.width, map.height));
+        assert_eq!((reloaded.tile_width, reloaded.tile_height), (map.tile_width, map.tile_height));
+        assert_eq!(reloaded.tilesets.len(), map.tilesets.len());
+        for (reloaded, tileset) in reloaded.tilesets.iter().zip(map.tilesets.iter()) {
+            assert_eq!(reloaded.first_gid, tileset.first_gid);
+            assert_eq!(reloaded.name, tileset.name);
+            assert_eq!(reloaded.images[0].source, tileset.images[0].source);
+        }
+        assert_eq!(reloaded.layers.len(), map.layers.len());
+        for (reloaded, layer) in reloaded.layers.iter().zip(map.layers.iter()) {
+            assert_eq!(reloaded.name, layer.name);
+            assert_eq!(reloaded.visible, layer.visible);
+            assert_eq!(reloaded.tiles, layer.tiles);
+        }
+        assert_eq!(read_draw_order(&saved[..]).unwrap(), draw_order);
+    }
+
+    #[test]
+    fn round_trips_map_as_csv() {
+        round_trip(DataEncoding::Csv);
+    }
+
+    #[test]
+    fn round_trips_map_as_base64_zlib() {
+        round_trip(DataEncoding::Base64Zlib);
+    }
+
+    #[test]
+    fn keeps_z_ordered_layers_and_object_groups_in_place() {
+        let data = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.0" orientation="orthogonal" renderorder="left-up" width="3" height="2" tilewidth="32" tileheight="32">
+ <objectgroup name="entities">
+  <properties><property name="z" value="2.5"/></properties>
+  <object id="1" name="start" type="player_spawn" x="32" y="32" width="32" height="32"/>
+ </objectgroup>
+ <layer name="canopy" width="3" height="2">
+  <properties><property name="z" value="5"/></properties>
+  <data encoding="csv">1,0,0,
+0,0,2</data>
+ </layer>
+ <layer name="ground" width="3" height="2">
+  <data encoding="csv">3,3,3,
+4,4,4</data>
+ </layer>
+ <objectgroup name="triggers">
+  <properties><property name="note" value="a &quot;quoted&quot; note"/></properties>
+  <object id="2" name="exit" type="trigger" x="64" y="0" width="32" height="64"/>
+ </objectgroup>
+</map>"#;
+        let map = tiled::parse(data.as_bytes()).unwrap();
+        let layout = read_layout(data.as_bytes()).unwrap();
+
+        let mut saved = Vec::new();
+        write_map(&map, &layout, DataEncoding::Csv, &mut saved).unwrap();
+        let reloaded = tiled::parse(&saved[..]).unwrap();
+
+        assert_eq!(read_layout(&saved[..]).unwrap(), layout);
+        assert_eq!(read_draw_order(&saved[..]).unwrap(), read_draw_order(data.as_bytes()).unwrap());
+        let names: Vec<&str> = reloaded.layers.iter().map(|layer| layer.name.as_str()).collect();
+        assert_eq!(names, vec!["canopy", "ground"]);
+        assert_eq!(reloaded.layers[0].tiles, map.layers[0].tiles);
+        let groups: Vec<(&str, usize)> = reloaded.object_groups.iter().map(|group| (group.name.as_str(), group.objects.len())).collect();
+        assert_eq!(groups, vec![("entities", 1), ("triggers", 1)]);
+    }
+
+    #[test]
+    fn encoded_data_decodes_to_the_same_gids() {
+        let tiles = vec![vec![1, 2, 0x80000003], vec![0, 4, 5]];
+        let flat: Vec<u32> = tiles.iter().flat_map(|row| row.iter().cloned()).collect();
+
+        assert_eq!(decode_gids(&encode_csv(&tiles), Some("csv"), None).unwrap(), flat);
+        let encoded = encode_base64_zlib(&tiles).unwrap();
+        assert_eq!(decode_gids(&encoded, Some("base64"), Some("zlib")).unwrap(), flat);
+    }
+
+    #[test]
+    fn escapes_attribute_values() {
+        assert_eq!(escape("a \"door\" <to> b & c's"), "a &quot;door&quot; &lt;to&gt; b &amp; c&apos;s");
+    }
+}